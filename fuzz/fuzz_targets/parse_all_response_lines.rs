@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    let page = shake_bot::parsing::parse_all_response_lines(&mut &*input);
+    for diagnostic in page.diagnostics {
+        assert!(diagnostic.line >= 1);
+        assert!(!diagnostic.error.is_empty());
+    }
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    let _ = shake_bot::parsing::parse_response(&mut &*input);
+    let _ = shake_bot::parsing::parse_response_line(&mut &*input);
+});
@@ -0,0 +1,406 @@
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::StatusCode;
+
+/// How much of a file to fetch from each end when probing it remotely
+const PROBE_BYTES: u64 = 64 * 1024;
+
+/// Discord won't take attachments larger than this without a boosted server
+pub const MAX_DOWNLOAD_BYTES: u64 = 8 * 1024 * 1024;
+
+/// How long a download may take, retries included
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What we know about a response's audio file without downloading all of it
+#[derive(Debug, Default, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct AudioInfo {
+    /// ie `audio/mpeg` or `application/ogg`
+    pub mime: Option<String>,
+    /// Size of the file in bytes
+    pub size: Option<u64>,
+    pub duration: Option<Duration>,
+}
+
+impl AudioInfo {
+    pub fn format(&self) -> Option<AudioFormat> {
+        self.mime.as_deref().and_then(AudioFormat::from_mime)
+    }
+
+    /// Reads everything there is to know from a whole file
+    pub fn probe(bytes: &[u8]) -> Self {
+        let format = AudioFormat::sniff(bytes);
+        let size = bytes.len() as u64;
+        Self {
+            mime: format.map(|f| f.mime().to_string()),
+            size: Some(size),
+            duration: format.and_then(|f| f.duration(bytes, bytes, size)),
+        }
+    }
+}
+
+/// The audio containers the wikis serve
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Mp3,
+    Ogg,
+    Wav,
+}
+
+impl AudioFormat {
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        let mime = mime.to_lowercase();
+        match mime.as_str() {
+            "audio/mpeg" | "audio/mp3" => Some(Self::Mp3),
+            "audio/wav" | "audio/wave" | "audio/x-wav" => Some(Self::Wav),
+            _ if mime.contains("ogg") || mime.contains("opus") => Some(Self::Ogg),
+            _ => None,
+        }
+    }
+
+    /// Works out the format from the magic bytes at the start of a file
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"OggS") {
+            Some(Self::Ogg)
+        } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WAVE") {
+            Some(Self::Wav)
+        } else if bytes.starts_with(b"ID3") || Mp3Frame::parse(bytes, 0).is_some() {
+            Some(Self::Mp3)
+        } else {
+            None
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Self::Mp3 => "audio/mpeg",
+            Self::Ogg => "audio/ogg",
+            Self::Wav => "audio/wav",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Mp3 => "mp3",
+            Self::Ogg => "ogg",
+            Self::Wav => "wav",
+        }
+    }
+
+    /// Works out how long a file plays for from its headers. `head` and `tail`
+    /// are the start and end of the file, which may be the same slice when
+    /// the whole file is at hand, and `size` is the size of the whole file
+    pub fn duration(self, head: &[u8], tail: &[u8], size: u64) -> Option<Duration> {
+        match self {
+            Self::Mp3 => mp3_duration(head, size),
+            Self::Ogg => ogg_duration(head, tail),
+            Self::Wav => wav_duration(head),
+        }
+    }
+}
+
+/// The length of an ID3v2 tag at the start of a file, if there is one
+fn id3_length(bytes: &[u8]) -> usize {
+    match bytes.get(..10) {
+        Some(header) if header.starts_with(b"ID3") => {
+            // the size is stored 7 bits to a byte
+            let size = header[6..10]
+                .iter()
+                .fold(0usize, |size, b| (size << 7) | (*b & 0x7f) as usize);
+            let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+            10 + size + footer
+        }
+        _ => 0,
+    }
+}
+
+/// An MPEG layer III frame header
+#[derive(Debug)]
+struct Mp3Frame {
+    /// Where the frame starts
+    offset: usize,
+    mpeg1: bool,
+    mono: bool,
+    /// In kbit/s
+    bitrate: u32,
+    sample_rate: u32,
+}
+
+impl Mp3Frame {
+    fn parse(bytes: &[u8], offset: usize) -> Option<Self> {
+        let header = bytes.get(offset..offset + 4)?;
+        if header[0] != 0xff || header[1] & 0xe0 != 0xe0 {
+            return None;
+        }
+        let version = (header[1] >> 3) & 0b11;
+        let layer = (header[1] >> 1) & 0b11;
+        // only layer III, version 1 is reserved
+        if layer != 0b01 || version == 0b01 {
+            return None;
+        }
+        let mpeg1 = version == 0b11;
+        let bitrates: [u32; 15] = if mpeg1 {
+            [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320]
+        } else {
+            [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160]
+        };
+        let bitrate = *bitrates.get((header[2] >> 4) as usize)?;
+        let rates: [u32; 3] = match version {
+            0b11 => [44100, 48000, 32000],
+            0b10 => [22050, 24000, 16000],
+            _ => [11025, 12000, 8000],
+        };
+        let sample_rate = *rates.get(((header[2] >> 2) & 0b11) as usize)?;
+        if bitrate == 0 {
+            return None;
+        }
+        Some(Self {
+            offset,
+            mpeg1,
+            mono: header[3] >> 6 == 0b11,
+            bitrate,
+            sample_rate,
+        })
+    }
+
+    /// The first frame after any ID3 tag
+    fn find(bytes: &[u8]) -> Option<Self> {
+        let start = id3_length(bytes);
+        (start..bytes.len().saturating_sub(3)).find_map(|offset| Self::parse(bytes, offset))
+    }
+
+    fn samples_per_frame(&self) -> u32 {
+        if self.mpeg1 {
+            1152
+        } else {
+            576
+        }
+    }
+
+    /// The frame count from a Xing, Info or VBRI header, which variable bitrate
+    /// files put in their first frame
+    fn frame_count(&self, bytes: &[u8]) -> Option<u32> {
+        let side_info = match (self.mpeg1, self.mono) {
+            (true, false) => 32,
+            (true, true) | (false, false) => 17,
+            (false, true) => 9,
+        };
+        let read_u32 = |at: usize| {
+            bytes
+                .get(at..at + 4)
+                .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        };
+        let xing = self.offset + 4 + side_info;
+        match bytes.get(xing..xing + 4) {
+            Some(b"Xing" | b"Info") if read_u32(xing + 4)? & 1 != 0 => return read_u32(xing + 8),
+            _ => (),
+        }
+        let vbri = self.offset + 4 + 32;
+        match bytes.get(vbri..vbri + 4) {
+            Some(b"VBRI") => read_u32(vbri + 14),
+            _ => None,
+        }
+    }
+}
+
+fn mp3_duration(head: &[u8], size: u64) -> Option<Duration> {
+    let frame = Mp3Frame::find(head)?;
+    let seconds = match frame.frame_count(head) {
+        Some(frames) => {
+            frames as f64 * frame.samples_per_frame() as f64 / frame.sample_rate as f64
+        }
+        // constant bitrate, so the size gives the length away
+        None => {
+            let audio_bytes = size.checked_sub(frame.offset as u64)?;
+            audio_bytes as f64 * 8.0 / (frame.bitrate as f64 * 1000.0)
+        }
+    };
+    Some(Duration::from_secs_f64(seconds))
+}
+
+fn ogg_duration(head: &[u8], tail: &[u8]) -> Option<Duration> {
+    // the first page holds the codec's identification header
+    let segments = *head.get(26)? as usize;
+    let packet = head.get(27 + segments..)?;
+    let (sample_rate, pre_skip) = if packet.starts_with(b"\x01vorbis") {
+        let rate = packet.get(12..16)?;
+        (u32::from_le_bytes([rate[0], rate[1], rate[2], rate[3]]), 0)
+    } else if packet.starts_with(b"OpusHead") {
+        // opus granule positions always count at 48kHz
+        let skip = packet.get(10..12)?;
+        (48000, u16::from_le_bytes([skip[0], skip[1]]) as u64)
+    } else {
+        return None;
+    };
+    if sample_rate == 0 {
+        return None;
+    }
+
+    // the granule position of the last page is the number of samples
+    let last_page = tail
+        .windows(4)
+        .rposition(|w| w == b"OggS")
+        .filter(|at| tail.len() >= at + 14)?;
+    let granule = u64::from_le_bytes(tail[last_page + 6..last_page + 14].try_into().ok()?);
+    let samples = granule.checked_sub(pre_skip)?;
+    Some(Duration::from_secs_f64(samples as f64 / sample_rate as f64))
+}
+
+fn wav_duration(head: &[u8]) -> Option<Duration> {
+    let read_u32 = |at: usize| {
+        head.get(at..at + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let mut byte_rate = None;
+    let mut at = 12;
+    while let Some(id) = head.get(at..at + 4) {
+        let length = read_u32(at + 4)?;
+        match id {
+            b"fmt " => byte_rate = read_u32(at + 16),
+            b"data" => {
+                let byte_rate = byte_rate.filter(|rate| *rate > 0)?;
+                return Some(Duration::from_secs_f64(length as f64 / byte_rate as f64));
+            }
+            _ => (),
+        }
+        // chunks are padded to an even length
+        at += 8 + length as usize + (length as usize & 1);
+    }
+    None
+}
+
+/// Fills in whatever `info` is missing by fetching just the start, and for ogg
+/// files the end, of the file
+pub async fn probe_url(client: &crate::Client, url: &str, info: AudioInfo) -> AudioInfo {
+    let (head, total) = match fetch_range(client, url, format!("bytes=0-{}", PROBE_BYTES - 1)).await
+    {
+        Ok(head) => head,
+        Err(e) => {
+            tracing::warn!("Could not probe {}: {:?}", url, e);
+            return info;
+        }
+    };
+    let size = info.size.or(total).unwrap_or(head.len() as u64);
+    let Some(format) = AudioFormat::sniff(&head).or_else(|| info.format()) else {
+        return AudioInfo {
+            size: Some(size),
+            ..info
+        };
+    };
+
+    let tail = if format == AudioFormat::Ogg && size > head.len() as u64 {
+        match fetch_range(client, url, format!("bytes=-{}", PROBE_BYTES)).await {
+            Ok((tail, _)) => tail,
+            Err(e) => {
+                tracing::warn!("Could not probe the end of {}: {:?}", url, e);
+                vec![]
+            }
+        }
+    } else {
+        head.clone()
+    };
+
+    AudioInfo {
+        mime: info.mime.or_else(|| Some(format.mime().to_string())),
+        size: Some(size),
+        duration: info.duration.or_else(|| format.duration(&head, &tail, size)),
+    }
+}
+
+/// Fetches part of a file, along with the size of the whole file when the
+/// server says what it is
+async fn fetch_range(
+    client: &crate::Client,
+    url: &str,
+    range: String,
+) -> anyhow::Result<(Vec<u8>, Option<u64>)> {
+    let response = client
+        .get(url)
+        .header(RANGE, range)
+        .send()
+        .await?
+        .error_for_status()?;
+    // of the form `bytes 0-65535/123456`
+    let total = response
+        .headers()
+        .get(CONTENT_RANGE)
+        .and_then(|range| range.to_str().ok())
+        .and_then(|range| range.rsplit_once('/'))
+        .and_then(|(_, total)| total.parse().ok());
+    let bytes = response.bytes().await?;
+    Ok((bytes.to_vec(), total))
+}
+
+/// Why a clip could not be downloaded
+#[derive(Debug)]
+pub enum DownloadError {
+    /// The server answered, but not with the file
+    Status(StatusCode),
+    /// The file is larger than [`MAX_DOWNLOAD_BYTES`]
+    TooLarge,
+    TimedOut,
+    Network(anyhow::Error),
+}
+
+impl DownloadError {
+    /// Whether the link itself is broken, rather than the wiki being slow or
+    /// unreachable for a while
+    pub fn is_dead_link(&self) -> bool {
+        matches!(
+            self,
+            Self::Status(StatusCode::NOT_FOUND | StatusCode::GONE | StatusCode::FORBIDDEN)
+        )
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Status(status) => write!(f, "server answered with {}", status),
+            Self::TooLarge => write!(f, "file is larger than {} bytes", MAX_DOWNLOAD_BYTES),
+            Self::TimedOut => write!(f, "timed out after {:?}", DOWNLOAD_TIMEOUT),
+            Self::Network(e) => write!(f, "{:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+/// Downloads a clip, giving up on files too large to attach and on servers
+/// that take too long
+pub async fn download(client: &crate::Client, url: &str) -> Result<Vec<u8>, DownloadError> {
+    let download = async {
+        let mut response = client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| DownloadError::Network(e.into()))?;
+        if !response.status().is_success() {
+            return Err(DownloadError::Status(response.status()));
+        }
+        if response
+            .content_length()
+            .is_some_and(|length| length > MAX_DOWNLOAD_BYTES)
+        {
+            return Err(DownloadError::TooLarge);
+        }
+        // the length isn't always given up front, so keep checking
+        let mut bytes = vec![];
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| DownloadError::Network(e.into()))?
+        {
+            bytes.extend_from_slice(&chunk);
+            if bytes.len() as u64 > MAX_DOWNLOAD_BYTES {
+                return Err(DownloadError::TooLarge);
+            }
+        }
+        Ok(bytes)
+    };
+    tokio::time::timeout(DOWNLOAD_TIMEOUT, download)
+        .await
+        .unwrap_or(Err(DownloadError::TimedOut))
+}
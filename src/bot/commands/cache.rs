@@ -0,0 +1,80 @@
+use super::Context;
+use anyhow::Error;
+
+use crate::cache::AudioCache;
+use crate::response::verify_links as verify;
+
+/// How many clips to download at once while warming the cache
+const PREFETCH_CONCURRENCY: usize = 8;
+
+/// Download every voice line into the local audio cache
+#[poise::command(slash_command, owners_only, hide_in_help)]
+pub async fn prefetch(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let urls = {
+        let data = crate::DATA.get().unwrap().lock().unwrap();
+        data.response_database
+            .responses
+            .iter()
+            .map(|r| r.response_link.clone())
+            .collect::<Vec<_>>()
+    };
+    let cache = AudioCache::global();
+    let report = cache
+        .prefetch(crate::Client::shared(), urls, PREFETCH_CONCURRENCY)
+        .await;
+
+    let mut summary = format!(
+        "Cached {} clips, {} failed. The cache holds {} of {} MB",
+        report.fetched,
+        report.failed,
+        cache.size() / (1024 * 1024),
+        cache.max_size() / (1024 * 1024),
+    );
+    if report.skipped > 0 {
+        summary.push_str(&format!(", {} clips did not fit", report.skipped));
+    }
+    ctx.say(summary).await?;
+    Ok(())
+}
+
+/// How many links to check at once
+const VERIFY_CONCURRENCY: usize = 16;
+/// How many unresolved links to list in the report
+const MAX_LISTED_LINKS: usize = 20;
+
+/// Check every voice line link and repair the ones that moved
+#[poise::command(slash_command, owners_only, hide_in_help)]
+pub async fn verify_links(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let checks = {
+        let data = crate::DATA.get().unwrap().lock().unwrap();
+        data.response_database.links_to_check()
+    };
+    let report = verify(crate::Client::shared(), checks, VERIFY_CONCURRENCY).await;
+    {
+        let mut data = crate::DATA.get().unwrap().lock().unwrap();
+        data.response_database.apply_link_report(&report);
+    }
+
+    let mut summary = format!(
+        "Checked {} links: {} repaired, {} unresolved, {} could not be reached",
+        report.checked,
+        report.repaired.len(),
+        report.unresolved.len(),
+        report.unreachable,
+    );
+    for check in report.unresolved.iter().take(MAX_LISTED_LINKS) {
+        summary.push_str(&format!("\n- `{}` <{}>", check.file, check.link));
+    }
+    if report.unresolved.len() > MAX_LISTED_LINKS {
+        summary.push_str(&format!(
+            "\n- and {} more",
+            report.unresolved.len() - MAX_LISTED_LINKS
+        ));
+    }
+    for chunk in crate::bot::split_large_message(&summary) {
+        ctx.say(chunk).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,378 @@
+use super::Context;
+use anyhow::Error;
+use poise::serenity_prelude::{Channel, Mentionable as _};
+use poise::ChoiceParameter as _;
+
+use crate::guild::{ClipMode, Cooldown, MatchMode};
+use crate::response::{Game, PageKind};
+
+/// Change how the bot behaves in this server
+#[poise::command(
+    slash_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    default_member_permissions = "MANAGE_GUILD",
+    subcommands(
+        "config_show",
+        "config_triggers",
+        "config_channel",
+        "config_cooldown",
+        "config_match",
+        "config_clips",
+        "config_language",
+        "sources"
+    )
+)]
+pub async fn config(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show this server's settings
+#[poise::command(slash_command, guild_only, rename = "show")]
+pub async fn config_show(ctx: Context<'_>) -> Result<(), Error> {
+    let settings = {
+        let data = crate::DATA.get().unwrap().lock().unwrap();
+        data.guild_settings(ctx.guild_id())
+    };
+    let list = |names: Vec<String>, empty: &str| match names.is_empty() {
+        true => empty.to_string(),
+        false => names.join(", "),
+    };
+    let mentions = |ids: &[String]| ids.iter().map(|id| format!("<#{}>", id)).collect();
+    let allowed = list(mentions(&settings.allowed_channels), "every channel");
+    let denied = list(mentions(&settings.denied_channels), "none");
+    let cooldown = |cooldown: Cooldown| match cooldown.is_none() {
+        true => "none".to_string(),
+        false => format!(
+            "{} messages and {} seconds",
+            cooldown.messages, cooldown.seconds
+        ),
+    };
+    let games = list(
+        settings
+            .games
+            .iter()
+            .map(|g| g.name().to_string())
+            .collect(),
+        "every game",
+    );
+    let heroes = list(settings.heroes, "every hero");
+    let excluded = list(
+        settings
+            .excluded_kinds
+            .iter()
+            .map(|k| k.name().to_string())
+            .collect(),
+        "nothing",
+    );
+    let lines = [
+        (
+            "Triggers",
+            if settings.triggers { "on" } else { "off" }.to_string(),
+        ),
+        ("Allowed channels", allowed),
+        ("Denied channels", denied),
+        ("Channel cooldown", cooldown(settings.cooldown)),
+        ("Member cooldown", cooldown(settings.user_cooldown)),
+        ("Matching", settings.match_mode.name().to_string()),
+        ("Clips", settings.clips.name().to_string()),
+        (
+            "Language",
+            settings.language.unwrap_or_else(|| "any".to_string()),
+        ),
+        ("Volume", format!("{}%", settings.volume.unwrap_or(100))),
+        ("Games", games),
+        ("Heroes", heroes),
+        ("Left out", excluded),
+    ];
+    let content = lines
+        .iter()
+        .map(|(name, value)| format!("**{}:** {}", name, value))
+        .collect::<Vec<_>>()
+        .join("\n");
+    ctx.send(
+        poise::CreateReply::default()
+            .content(content)
+            .allowed_mentions(Default::default()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Let messages trigger hero responses, or stop them
+#[poise::command(slash_command, guild_only, rename = "triggers")]
+pub async fn config_triggers(
+    ctx: Context<'_>,
+    #[description = "Whether messages can trigger responses"] enabled: bool,
+) -> Result<(), Error> {
+    {
+        let mut data = crate::DATA.get().unwrap().lock().unwrap();
+        data.guild_settings_mut(ctx.guild_id().unwrap()).triggers = enabled;
+    }
+    match enabled {
+        true => ctx.say("Messages can trigger hero responses").await?,
+        false => {
+            ctx.say("Messages no longer trigger hero responses, commands still work")
+                .await?
+        }
+    };
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum ChannelRule {
+    #[name = "Allow"]
+    Allow,
+    #[name = "Deny"]
+    Deny,
+    /// Follow the rule of the category, or the server's default
+    #[name = "Clear"]
+    Clear,
+}
+
+/// Allow or deny responses in a channel or a whole category
+#[poise::command(slash_command, guild_only, rename = "channel")]
+pub async fn config_channel(
+    ctx: Context<'_>,
+    #[description = "The channel or category"] channel: Channel,
+    #[description = "Whether responses can be triggered there"] rule: ChannelRule,
+) -> Result<(), Error> {
+    let id = channel.id().to_string();
+    let allowed_anywhere = {
+        let mut data = crate::DATA.get().unwrap().lock().unwrap();
+        let settings = data.guild_settings_mut(ctx.guild_id().unwrap());
+        settings.allowed_channels.retain(|c| *c != id);
+        settings.denied_channels.retain(|c| *c != id);
+        match rule {
+            ChannelRule::Allow => settings.allowed_channels.push(id),
+            ChannelRule::Deny => settings.denied_channels.push(id),
+            ChannelRule::Clear => {}
+        }
+        settings.allowed_channels.is_empty()
+    };
+    let content = match rule {
+        ChannelRule::Allow => format!("Responses can be triggered in {}", channel.mention()),
+        ChannelRule::Deny => format!("Responses are never triggered in {}", channel.mention()),
+        ChannelRule::Clear if allowed_anywhere => format!(
+            "{} has no rule, so it follows its category or else allows responses",
+            channel.mention()
+        ),
+        ChannelRule::Clear => format!(
+            "{} has no rule, so it follows its category or else denies responses",
+            channel.mention()
+        ),
+    };
+    ctx.say(content).await?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum CooldownScope {
+    #[name = "Each channel"]
+    Channel,
+    #[name = "Each member"]
+    User,
+}
+
+/// Wait between triggered responses in a channel, or for a member
+#[poise::command(slash_command, guild_only, rename = "cooldown")]
+pub async fn config_cooldown(
+    ctx: Context<'_>,
+    #[description = "Messages that have to be sent between responses"] messages: Option<u32>,
+    #[description = "Seconds that have to pass between responses"] seconds: Option<u32>,
+    #[description = "Who waits, each channel when not given"] scope: Option<CooldownScope>,
+) -> Result<(), Error> {
+    let scope = scope.unwrap_or(CooldownScope::Channel);
+    let cooldown = {
+        let mut data = crate::DATA.get().unwrap().lock().unwrap();
+        let settings = data.guild_settings_mut(ctx.guild_id().unwrap());
+        let cooldown = match scope {
+            CooldownScope::Channel => &mut settings.cooldown,
+            CooldownScope::User => &mut settings.user_cooldown,
+        };
+        cooldown.messages = messages.unwrap_or(0);
+        cooldown.seconds = seconds.unwrap_or(0);
+        *cooldown
+    };
+    let content = match (cooldown.is_none(), scope) {
+        (true, CooldownScope::Channel) => "Channels have no cooldown".to_string(),
+        (true, CooldownScope::User) => "Members have no cooldown".to_string(),
+        (false, CooldownScope::Channel) => format!(
+            "Each channel waits for {} messages and {} seconds between responses",
+            cooldown.messages, cooldown.seconds
+        ),
+        (false, CooldownScope::User) => format!(
+            "Each member sends {} messages and waits {} seconds between responses they trigger",
+            cooldown.messages, cooldown.seconds
+        ),
+    };
+    ctx.say(content).await?;
+    Ok(())
+}
+
+/// Choose how much of a message has to match a line
+#[poise::command(slash_command, guild_only, rename = "match")]
+pub async fn config_match(
+    ctx: Context<'_>,
+    #[description = "How messages are matched"] mode: MatchMode,
+) -> Result<(), Error> {
+    {
+        let mut data = crate::DATA.get().unwrap().lock().unwrap();
+        data.guild_settings_mut(ctx.guild_id().unwrap()).match_mode = mode;
+    }
+    ctx.say(format!("Matching lines: {}", mode.name())).await?;
+    Ok(())
+}
+
+/// Choose how response clips are posted
+#[poise::command(slash_command, guild_only, rename = "clips")]
+pub async fn config_clips(
+    ctx: Context<'_>,
+    #[description = "Where clips go"] mode: ClipMode,
+) -> Result<(), Error> {
+    if mode == ClipMode::VoiceMessage && !cfg!(feature = "voice-messages") {
+        ctx.say("This bot was built without voice message support")
+            .await?;
+        return Ok(());
+    }
+    {
+        let mut data = crate::DATA.get().unwrap().lock().unwrap();
+        data.guild_settings_mut(ctx.guild_id().unwrap()).clips = mode;
+    }
+    ctx.say(format!("Posting clips: {}", mode.name())).await?;
+    Ok(())
+}
+
+/// Set the language this server's hero responses should prefer
+#[poise::command(slash_command, guild_only, rename = "language")]
+pub async fn config_language(
+    ctx: Context<'_>,
+    #[description = "Language code such as en or ru, leave empty to clear"] code: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let code = code
+        .map(|c| c.trim().to_lowercase())
+        .filter(|c| !c.is_empty());
+    {
+        let mut data = crate::DATA.get().unwrap().lock().unwrap();
+        data.guild_settings_mut(guild_id).language = code.clone();
+    }
+    match code {
+        Some(code) => {
+            ctx.say(format!("Hero responses will prefer `{}`", code))
+                .await?
+        }
+        None => {
+            ctx.say("Hero responses no longer prefer a language")
+                .await?
+        }
+    };
+    Ok(())
+}
+
+/// Choose which games, heroes and kinds of lines this server gets
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands("sources_game", "sources_heroes", "sources_kind")
+)]
+pub async fn sources(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Switch a game's lines on or off for this server
+#[poise::command(slash_command, guild_only, rename = "game")]
+pub async fn sources_game(
+    ctx: Context<'_>,
+    #[description = "The game"] game: Game,
+    #[description = "Whether its lines should be used"] enabled: bool,
+) -> Result<(), Error> {
+    let games = {
+        let mut data = crate::DATA.get().unwrap().lock().unwrap();
+        let games = &mut data.guild_settings_mut(ctx.guild_id().unwrap()).games;
+        // an empty list means every game
+        if games.is_empty() {
+            games.extend(Game::ALL);
+        }
+        games.retain(|g| *g != game);
+        if enabled {
+            games.push(game);
+        }
+        if games.is_empty() || Game::ALL.iter().all(|g| games.contains(g)) {
+            games.clear();
+        }
+        games.len()
+    };
+    match (enabled, games) {
+        (true, _) => ctx.say(format!("{} lines are on", game.name())).await?,
+        // switching off the last game switches every game back on
+        (false, 0) => {
+            ctx.say("Every game's lines are on, at least one has to stay on")
+                .await?
+        }
+        (false, _) => ctx.say(format!("{} lines are off", game.name())).await?,
+    };
+    Ok(())
+}
+
+/// Only use lines from some heroes in this server
+#[poise::command(slash_command, guild_only, rename = "heroes")]
+pub async fn sources_heroes(
+    ctx: Context<'_>,
+    #[description = "Hero names separated by commas, leave empty for every hero"] heroes: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    let names = heroes
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<_>>();
+    let unknown = {
+        let mut data = crate::DATA.get().unwrap().lock().unwrap();
+        let unknown = names
+            .iter()
+            .filter(|name| data.response_database.get_hero_family(name).is_empty())
+            .cloned()
+            .collect::<Vec<_>>();
+        if unknown.is_empty() {
+            data.guild_settings_mut(ctx.guild_id().unwrap()).heroes = names.clone();
+        }
+        unknown
+    };
+    if !unknown.is_empty() {
+        ctx.say(format!("No heroes named {}", unknown.join(", ")))
+            .await?;
+    } else if names.is_empty() {
+        ctx.say("Lines from every hero are on").await?;
+    } else {
+        ctx.say(format!("Only lines from {} are on", names.join(", ")))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Switch a kind of line on or off for this server, ie announcer packs
+#[poise::command(slash_command, guild_only, rename = "kind")]
+pub async fn sources_kind(
+    ctx: Context<'_>,
+    #[description = "The kind of page the lines come from"] kind: PageKind,
+    #[description = "Whether its lines should be used"] enabled: bool,
+) -> Result<(), Error> {
+    {
+        let mut data = crate::DATA.get().unwrap().lock().unwrap();
+        let excluded = &mut data
+            .guild_settings_mut(ctx.guild_id().unwrap())
+            .excluded_kinds;
+        excluded.retain(|k| *k != kind);
+        if !enabled {
+            excluded.push(kind);
+        }
+    }
+    match enabled {
+        true => ctx.say(format!("{} lines are on", kind.name())).await?,
+        false => ctx.say(format!("{} lines are off", kind.name())).await?,
+    };
+    Ok(())
+}
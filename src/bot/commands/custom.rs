@@ -0,0 +1,207 @@
+use super::dota::find_response;
+use super::Context;
+use anyhow::Error;
+use poise::serenity_prelude::Attachment;
+
+use crate::audio::{AudioFormat, MAX_DOWNLOAD_BYTES};
+use crate::guild::{self, CustomReply, CustomResponse, MAX_CUSTOM_RESPONSES};
+use crate::parsing::escape_markdown;
+use crate::response::ResponseFilter;
+
+/// Manage this server's own trigger phrases
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands("custom_add", "custom_remove", "custom_list")
+)]
+pub async fn custom(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Reply to a phrase with some text, an audio file or a hero response
+#[poise::command(
+    slash_command,
+    guild_only,
+    rename = "add",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn custom_add(
+    ctx: Context<'_>,
+    #[description = "The phrase to reply to"] trigger: String,
+    #[description = "Reply with this text"]
+    #[max_length = 2000]
+    text: Option<String>,
+    #[description = "Reply with this mp3, ogg or wav file"] audio: Option<Attachment>,
+    #[description = "Reply with the hero response that best matches this"] response: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let trigger = crate::process_text(&trigger);
+    if trigger.is_empty() {
+        ctx.say("The trigger needs at least one word").await?;
+        return Ok(());
+    }
+    let replies = text.is_some() as u8 + audio.is_some() as u8 + response.is_some() as u8;
+    if replies != 1 {
+        ctx.say("Give exactly one of `text`, `audio` or `response`")
+            .await?;
+        return Ok(());
+    }
+    let full = {
+        let data = crate::DATA.get().unwrap().lock().unwrap();
+        let settings = data.guild_settings(Some(guild_id));
+        settings.custom_response(&trigger).is_none()
+            && settings.custom_responses.len() >= MAX_CUSTOM_RESPONSES
+    };
+    if full {
+        ctx.say(format!(
+            "This server already has {} custom responses",
+            MAX_CUSTOM_RESPONSES
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let reply = if let Some(text) = text {
+        CustomReply::Text(text)
+    } else if let Some(audio) = audio {
+        if audio.size as u64 > MAX_DOWNLOAD_BYTES {
+            ctx.say("That file is too large").await?;
+            return Ok(());
+        }
+        ctx.defer().await?;
+        let bytes = audio.download().await?;
+        if AudioFormat::sniff(&bytes).is_none() {
+            ctx.say("That isn't an mp3, ogg or wav file").await?;
+            return Ok(());
+        }
+        CustomReply::Audio {
+            file_name: audio.filename.clone(),
+            hash: guild::save_custom_audio(&bytes).await?,
+        }
+    } else {
+        let phrase = response.unwrap_or_default();
+        let Some(res) = find_response(ctx, Some(&phrase), None, ResponseFilter::default()) else {
+            ctx.say("No response found").await?;
+            return Ok(());
+        };
+        if res.file.is_empty() {
+            ctx.say("That response can't be used until the next database update")
+                .await?;
+            return Ok(());
+        }
+        CustomReply::Response { file: res.file }
+    };
+
+    let description = reply.describe();
+    let custom = CustomResponse {
+        trigger: trigger.clone(),
+        reply,
+        author: ctx.author().id.to_string(),
+    };
+    let replaced = {
+        let mut data = crate::DATA.get().unwrap().lock().unwrap();
+        let customs = &mut data.guild_settings_mut(guild_id).custom_responses;
+        let replaced = customs
+            .iter()
+            .position(|c| c.trigger == trigger)
+            .map(|i| customs.remove(i));
+        customs.push(custom);
+        replaced
+    };
+    if let Some(replaced) = replaced {
+        forget_audio(&replaced.reply).await;
+    }
+    ctx.say(format!(
+        "`{}` now replies with {}",
+        trigger,
+        escape_markdown(&description)
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Stop replying to one of this server's phrases
+#[poise::command(
+    slash_command,
+    guild_only,
+    rename = "remove",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn custom_remove(
+    ctx: Context<'_>,
+    #[description = "The phrase to stop replying to"] trigger: String,
+) -> Result<(), Error> {
+    let trigger = crate::process_text(&trigger);
+    let removed = {
+        let mut data = crate::DATA.get().unwrap().lock().unwrap();
+        let customs = &mut data
+            .guild_settings_mut(ctx.guild_id().unwrap())
+            .custom_responses;
+        customs
+            .iter()
+            .position(|c| c.trigger == trigger)
+            .map(|i| customs.remove(i))
+    };
+    match removed {
+        Some(removed) => {
+            forget_audio(&removed.reply).await;
+            ctx.say(format!("Removed `{}`", trigger)).await?
+        }
+        None => {
+            ctx.say(format!("There's no custom response for `{}`", trigger))
+                .await?
+        }
+    };
+    Ok(())
+}
+
+/// List this server's own trigger phrases
+#[poise::command(slash_command, guild_only, rename = "list")]
+pub async fn custom_list(ctx: Context<'_>) -> Result<(), Error> {
+    let customs = {
+        let data = crate::DATA.get().unwrap().lock().unwrap();
+        data.guild_settings(ctx.guild_id()).custom_responses
+    };
+    if customs.is_empty() {
+        ctx.say("This server has no custom responses").await?;
+        return Ok(());
+    }
+    // keep each message under Discord's limit without splitting a line
+    let mut messages = vec![String::new()];
+    for custom in &customs {
+        let line = format!(
+            "`{}`: {}\n",
+            custom.trigger,
+            escape_markdown(&custom.reply.describe())
+        );
+        if messages.last().unwrap().len() + line.len() > 2000 {
+            messages.push(String::new());
+        }
+        messages.last_mut().unwrap().push_str(&line);
+    }
+    for message in messages {
+        ctx.say(message).await?;
+    }
+    Ok(())
+}
+
+/// Deletes the clip behind a removed reply, unless another guild uses it too
+async fn forget_audio(reply: &CustomReply) {
+    let CustomReply::Audio { hash, .. } = reply else {
+        return;
+    };
+    let in_use = {
+        let data = crate::DATA.get().unwrap().lock().unwrap();
+        data.guilds.values().any(|settings| {
+            settings
+                .custom_responses
+                .iter()
+                .any(|c| matches!(&c.reply, CustomReply::Audio { hash: h, .. } if h == hash))
+        })
+    };
+    if !in_use {
+        if let Err(e) = guild::remove_custom_audio(hash).await {
+            tracing::warn!("Failed to remove custom audio {}: {:?}", hash, e);
+        }
+    }
+}
@@ -1,5 +1,13 @@
 use super::Context;
 use anyhow::Error;
+use poise::serenity_prelude::CreateAttachment;
+use std::time::Duration;
+
+use crate::audio::MAX_DOWNLOAD_BYTES;
+use crate::bot::dota::{character_response_embed, fetch_audio, send_response_clip};
+use crate::parsing::{escape_markdown, Rarity};
+use crate::response::{PageKind, Response, ResponseFilter};
+use crate::transcode::Pcm;
 
 /// Don't allow the bot to send hero responses to your messages
 #[poise::command(slash_command)]
@@ -23,3 +31,195 @@ pub async fn enable(ctx: Context<'_>) -> Result<(), Error> {
     ctx.say("You have enabled hero responses").await?;
     Ok(())
 }
+
+/// Searches for `phrase`, or picks a random line without one, from `hero` and
+/// its variants, among the lines the guild has switched on. Lines in the
+/// guild's language are preferred, falling back to any language
+pub(super) fn find_response(
+    ctx: Context<'_>,
+    phrase: Option<&str>,
+    hero: Option<&str>,
+    mut filter: ResponseFilter,
+) -> Option<Response> {
+    let data = crate::DATA.get().unwrap().lock().unwrap();
+    let db = &data.response_database;
+    if let Some(name) = hero {
+        filter.heroes = db.get_hero_family(name);
+        if filter.heroes.is_empty() {
+            return None;
+        }
+    }
+    let mut filter = data.guild_filter(ctx.guild_id(), filter)?;
+    let find = |filter: &ResponseFilter| match phrase {
+        Some(phrase) => db.search(phrase, filter),
+        None => db.random_response(filter),
+    };
+    find(&filter)
+        .or_else(|| {
+            filter.language = None;
+            find(&filter)
+        })
+        .cloned()
+}
+
+/// Find a hero response, optionally narrowed down by hero, subject or rarity
+#[poise::command(slash_command)]
+// every option of the slash command is an argument
+#[expect(clippy::too_many_arguments)]
+pub async fn dota(
+    ctx: Context<'_>,
+    #[description = "The phrase to search for"] phrase: Option<String>,
+    #[description = "Only lines spoken by this hero"] hero: Option<String>,
+    #[description = "Only lines about this hero, ability or item"] about: Option<String>,
+    #[description = "Only lines under this heading, ie Killing an enemy"] category: Option<String>,
+    #[description = "Only rare lines"] rare: Option<bool>,
+    #[description = "Skip arcana, skin and announcer pack lines"] base_only: Option<bool>,
+    #[description = "Only clips at most this many seconds long"]
+    #[min = 1]
+    max_seconds: Option<u32>,
+) -> Result<(), Error> {
+    let filter = ResponseFilter {
+        about,
+        category,
+        min_rarity: rare.unwrap_or(false).then_some(Rarity::Rare),
+        kinds: match base_only {
+            Some(true) => vec![PageKind::Hero],
+            _ => vec![],
+        },
+        max_duration: max_seconds.map(|secs| Duration::from_secs(secs.into())),
+        ..Default::default()
+    };
+    let res = find_response(ctx, phrase.as_deref(), hero.as_deref(), filter);
+
+    let Some(res) = res else {
+        ctx.say("No response found").await?;
+        return Ok(());
+    };
+
+    ctx.defer().await?;
+    let bytes = fetch_audio(&res).await;
+    let embed = character_response_embed(&res);
+    let reply = ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    let msg = reply.into_message().await?;
+    send_response_clip(bytes, &res, &msg, ctx.guild_id(), ctx.http()).await;
+    Ok(())
+}
+
+/// Most words `/say` will try to cover
+const MAX_SAY_WORDS: usize = 40;
+
+/// Silence left between the clips of a `/say` sentence
+const SAY_GAP: Duration = Duration::from_millis(150);
+
+/// Sample rate of the sentences `/say` puts together
+const SAY_SAMPLE_RATE: u32 = 24_000;
+
+/// Covers `text` with lines from `hero` and its variants, along with the name
+/// of the page each line came from, using only lines the guild has switched
+/// on. Lines in the guild's language are preferred, falling back to any
+/// language
+fn cover_sentence(
+    ctx: Context<'_>,
+    text: &str,
+    hero: Option<&str>,
+) -> Result<Vec<(Response, String)>, String> {
+    let data = crate::DATA.get().unwrap().lock().unwrap();
+    let db = &data.response_database;
+    let mut filter = ResponseFilter::default();
+    if let Some(name) = hero {
+        filter.heroes = db.get_hero_family(name);
+        if filter.heroes.is_empty() {
+            return Err(format!("No hero named {}", name));
+        }
+    }
+    let mut filter = data
+        .guild_filter(ctx.guild_id(), filter)
+        .ok_or_else(|| format!("{} is switched off in this server", hero.unwrap_or("")))?;
+    let pieces = db
+        .cover_sentence(text, &filter)
+        .or_else(|_| {
+            filter.language = None;
+            db.cover_sentence(text, &filter)
+        })
+        .map_err(|word| format!("Nobody says \"{}\"", word))?;
+    Ok(pieces
+        .into_iter()
+        .map(|res| {
+            let name = db.get_hero_name(res.hero_id).unwrap_or("Unknown");
+            (res.clone(), name.to_string())
+        })
+        .collect())
+}
+
+/// Piece a sentence together out of voice lines
+#[poise::command(slash_command)]
+pub async fn say(
+    ctx: Context<'_>,
+    #[description = "The sentence to say"] text: String,
+    #[description = "Only lines spoken by this hero"] hero: Option<String>,
+) -> Result<(), Error> {
+    if crate::process_text(&text).split(' ').count() > MAX_SAY_WORDS {
+        ctx.say(format!(
+            "That's too long, try at most {} words",
+            MAX_SAY_WORDS
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let cover = cover_sentence(ctx, &text, hero.as_deref());
+    let pieces = match cover {
+        Ok(pieces) if !pieces.is_empty() => pieces,
+        Ok(_) => {
+            ctx.say("Nothing to say").await?;
+            return Ok(());
+        }
+        Err(reason) => {
+            ctx.say(reason).await?;
+            return Ok(());
+        }
+    };
+
+    ctx.defer().await?;
+    let clips = futures::future::join_all(pieces.iter().map(|(res, _)| fetch_audio(res))).await;
+    let Some(clips) = clips.into_iter().collect::<Option<Vec<_>>>() else {
+        ctx.say("Couldn't download every clip, try again later")
+            .await?;
+        return Ok(());
+    };
+    // decoding takes long enough to stall the runtime
+    let wav = tokio::task::spawn_blocking(move || {
+        let clips = clips
+            .iter()
+            .map(|bytes| Pcm::decode(bytes))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        anyhow::Ok(Pcm::concat(&clips, SAY_GAP, SAY_SAMPLE_RATE).to_wav())
+    })
+    .await??;
+    if wav.len() as u64 > MAX_DOWNLOAD_BYTES {
+        ctx.say("That sentence came out too long to upload").await?;
+        return Ok(());
+    }
+
+    let transcript = pieces
+        .iter()
+        .enumerate()
+        .map(|(i, (res, hero))| {
+            format!(
+                "{}. **{}**: {}",
+                i + 1,
+                escape_markdown(hero),
+                res.markdown()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let attachment = CreateAttachment::bytes(wav, "say.wav");
+    ctx.send(
+        poise::CreateReply::default()
+            .content(transcript)
+            .attachment(attachment),
+    )
+    .await?;
+    Ok(())
+}
@@ -1,4 +1,9 @@
+pub mod cache;
+pub mod config;
+pub mod custom;
 pub mod dota;
+#[cfg(feature = "voice")]
+pub mod voice;
 
 use std::collections::HashMap;
 
@@ -11,10 +16,10 @@ pub type Context<'a> = poise::Context<'a, Data, Error>;
 #[derive(Debug, Clone, serde::Deserialize)]
 struct Copypastas(HashMap<String, Copypasta>);
 
+/// The file's `guild` field isn't used yet, so it isn't read
 #[derive(Debug, Clone, serde::Deserialize)]
 struct Copypasta {
     content: String,
-    guild: String,
 }
 
 async fn meme_helper(ctx: Context<'_>, text: &str) -> Result<(), Error> {
@@ -46,6 +51,23 @@ pub async fn copypasta(
     ctx: Context<'_>,
     #[description = "The name of the copypasta"] name: String,
 ) -> Result<(), Error> {
+    let settings = {
+        let data = crate::DATA.get().unwrap().lock().unwrap();
+        data.guild_settings(ctx.guild_id())
+    };
+    if settings.has_channel_rules()
+        && !settings.channel_allowed(
+            &super::channel_chain(ctx.serenity_context(), ctx.channel_id()).await,
+        )
+    {
+        ctx.send(
+            poise::CreateReply::default()
+                .content("Copypastas aren't allowed in this channel")
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
     let copypastas = serde_json::from_str::<Copypastas>(&std::fs::read_to_string(
         "copypastas.json",
     )?)?;
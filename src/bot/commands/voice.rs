@@ -0,0 +1,104 @@
+use super::dota::find_response;
+use super::Context;
+use anyhow::Error;
+
+use crate::bot::dota::{character_response_embed, fetch_audio};
+use crate::bot::voice::{self, Queued, MAX_QUEUE_LENGTH};
+use crate::response::ResponseFilter;
+
+/// Play a hero response in your voice channel
+#[poise::command(slash_command, guild_only)]
+pub async fn play(
+    ctx: Context<'_>,
+    #[description = "The phrase to search for"] phrase: Option<String>,
+    #[description = "Only lines spoken by this hero"] hero: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let Some(channel_id) = voice::member_channel(ctx.serenity_context(), guild_id, ctx.author().id)
+    else {
+        ctx.say("Join a voice channel first").await?;
+        return Ok(());
+    };
+    let filter = ResponseFilter::default();
+    let Some(res) = find_response(ctx, phrase.as_deref(), hero.as_deref(), filter) else {
+        ctx.say("No response found").await?;
+        return Ok(());
+    };
+
+    ctx.defer().await?;
+    let Some(bytes) = fetch_audio(&res).await else {
+        ctx.say("Couldn't get the audio for that response").await?;
+        return Ok(());
+    };
+    let queued = voice::play(ctx.serenity_context(), guild_id, channel_id, &res, bytes).await?;
+    let content = match queued {
+        Queued::At(0) => "Playing now".to_string(),
+        Queued::At(ahead) => format!("Queued behind {} clips", ahead),
+        Queued::Duplicate => "That response is already next in the queue".to_string(),
+        Queued::Full => format!("The queue is full, it holds {} clips", MAX_QUEUE_LENGTH),
+    };
+    let embed = character_response_embed(&res);
+    ctx.send(poise::CreateReply::default().content(content).embed(embed))
+        .await?;
+    Ok(())
+}
+
+/// List the responses waiting to be played
+#[poise::command(slash_command, guild_only)]
+pub async fn queue(ctx: Context<'_>) -> Result<(), Error> {
+    let queue = voice::queue(ctx.serenity_context(), ctx.guild_id().unwrap()).await?;
+    if queue.is_empty() {
+        ctx.say("Nothing is playing").await?;
+        return Ok(());
+    }
+    let lines = queue
+        .iter()
+        .enumerate()
+        .map(|(i, queued)| match i {
+            0 => format!("▶ **{}**: {}", queued.hero, queued.text),
+            _ => format!("{}. **{}**: {}", i, queued.hero, queued.text),
+        })
+        .collect::<Vec<_>>();
+    ctx.say(lines.join("\n")).await?;
+    Ok(())
+}
+
+/// Skip the response that is playing
+#[poise::command(slash_command, guild_only)]
+pub async fn skip(ctx: Context<'_>) -> Result<(), Error> {
+    if voice::skip(ctx.serenity_context(), ctx.guild_id().unwrap()).await? {
+        ctx.say("Skipped").await?;
+    } else {
+        ctx.say("Nothing is playing").await?;
+    }
+    Ok(())
+}
+
+/// Stop playing, clear the queue and leave the voice channel
+#[poise::command(slash_command, guild_only)]
+pub async fn stop(ctx: Context<'_>) -> Result<(), Error> {
+    voice::stop(ctx.serenity_context(), ctx.guild_id().unwrap()).await?;
+    ctx.say("Stopped").await?;
+    Ok(())
+}
+
+/// Set how loud responses play in voice channels
+#[poise::command(slash_command, guild_only, rename = "volume")]
+pub async fn config_volume(
+    ctx: Context<'_>,
+    #[description = "Volume in percent, 100 is unchanged"]
+    #[min = 0]
+    #[max = 200]
+    percent: u8,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let volume = {
+        let mut data = crate::DATA.get().unwrap().lock().unwrap();
+        let settings = data.guild_settings_mut(guild_id);
+        settings.volume = Some(percent);
+        settings.volume()
+    };
+    voice::set_volume(ctx.serenity_context(), guild_id, volume).await?;
+    ctx.say(format!("Volume set to {}%", percent)).await?;
+    Ok(())
+}
@@ -1,28 +1,33 @@
 use serenity::{
-    builder::{CreateEmbed, CreateEmbedFooter, CreateAttachment, CreateMessage, CreateThread, EditThread},
-    model::Color, all::Message, http::Http,
+    builder::{CreateEmbed, CreateEmbedAuthor, CreateEmbedFooter, CreateAttachment, CreateMessage, CreateThread, EditThread},
+    model::Color, all::{GuildId, Message}, http::Http,
 };
 
-use crate::{DATA, response::Response};
+use crate::{cache::AudioCache, guild::ClipMode, response::Response, DATA};
 
-pub fn character_response_embed(hero_id: i32) -> CreateEmbed {
+pub fn character_response_embed(res: &Response) -> CreateEmbed {
     let data = DATA.get().unwrap().lock().unwrap();
     let db = &data.response_database;
-    let footer_text = "Hero Responses".to_string();
-    let hero_name = db.get_hero_name(hero_id).unwrap_or("Unknown");
-    let hero_name_fmt = hero_name.to_string().replace(' ', "").to_lowercase();
-    let mut icon_url = db.get_icon_url(&hero_name_fmt);
-    if icon_url.is_none() {
-        let tmp = &hero_name_fmt.to_string().replace("announcerpack", "");
-        icon_url = db.get_icon_url(tmp);
-    }
+    let footer_text = if res.category.is_empty() {
+        "Hero Responses".to_string()
+    } else {
+        format!("Hero Responses • {}", res.category.join(" › "))
+    };
+    let base_name = db.get_base_hero_name(res.hero_id).unwrap_or("Unknown");
+    let hero_name = match db.get_variant(res.hero_id) {
+        Some(variant) if variant != base_name => format!("{} — {}", base_name, variant),
+        _ => base_name.to_string(),
+    };
+    let hero_name_fmt = base_name.replace(' ', "").to_lowercase();
+    let icon_url = db.get_icon_url(&hero_name_fmt);
 
     match icon_url {
         Some(url) => {
             let embed_footer = CreateEmbedFooter::new(footer_text).icon_url(url);
 
             CreateEmbed::new()
-                .description(hero_name)
+                .author(CreateEmbedAuthor::new(hero_name))
+                .description(res.markdown())
                 .colour(Color::BLUE)
                 .footer(embed_footer)
         }
@@ -30,27 +35,136 @@ pub fn character_response_embed(hero_id: i32) -> CreateEmbed {
             let embed_footer = CreateEmbedFooter::new(footer_text);
 
             CreateEmbed::new()
-                .description(hero_name)
+                .author(CreateEmbedAuthor::new(hero_name))
+                .description(res.markdown())
                 .colour(Color::BLUE)
                 .footer(embed_footer)
         }
     }
 }
 
-pub async fn dota_response_thread(bytes: Vec<u8>, res: &Response, msg: &Message, ctx_http: &Http) {
-    let attachment =
-        CreateAttachment::bytes(bytes, format!("{}.mp3", &res.original_text));
-    let message = CreateMessage::new().add_file(attachment);
+/// Gets the clip for a response, or `None` if it can't be had right now.
+/// Links that are gone for good are recorded so the next update can fix them
+pub async fn fetch_audio(res: &Response) -> Option<Vec<u8>> {
+    match AudioCache::global()
+        .fetch(crate::Client::shared(), &res.response_link)
+        .await
+    {
+        Ok(bytes) => Some(bytes),
+        Err(e) => {
+            tracing::error!("Failed to get audio for {}: {}", res.response_link, e);
+            if e.is_dead_link() {
+                DATA.get()
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .response_database
+                    .mark_dead_link(&res.response_link);
+            }
+            None
+        }
+    }
+}
+
+/// Posts the link to a response and, when it could be downloaded, its clip in
+/// a thread under `msg`
+pub async fn dota_response_thread(
+    bytes: Option<Vec<u8>>,
+    res: &Response,
+    msg: &Message,
+    ctx_http: &Http,
+) {
     let thread_builder = CreateThread::new(res.original_text.clone());
     let thread =
         msg.channel_id
             .create_thread_from_message(ctx_http, msg, thread_builder);
-    if let Ok(mut t) = thread.await {
-        t.say(ctx_http, &res.response_link).await.unwrap();
-        t.send_message(ctx_http, message).await.unwrap();
-        let edit_thread = EditThread::new().archived(true);
-        t.edit_thread(ctx_http, edit_thread).await.unwrap();
-    } else {
+    let Ok(mut t) = thread.await else {
         tracing::error!("Error creating thread");
+        return;
+    };
+    if let Err(e) = t.say(ctx_http, &res.response_link).await {
+        tracing::error!("Error sending response link: {:?}", e);
+    }
+    if let Some(bytes) = bytes {
+        let file_name = format!("{}.{}", &res.original_text, res.file_extension(&bytes));
+        let attachment = CreateAttachment::bytes(bytes, file_name);
+        let message = CreateMessage::new().add_file(attachment);
+        if let Err(e) = t.send_message(ctx_http, message).await {
+            tracing::error!("Error sending response audio: {:?}", e);
+        }
+    }
+    let edit_thread = EditThread::new().archived(true);
+    if let Err(e) = t.edit_thread(ctx_http, edit_thread).await {
+        tracing::error!("Error archiving thread: {:?}", e);
+    }
+}
+
+/// Transcodes a clip and posts it as a voice message in the channel of `msg`
+#[cfg(feature = "voice-messages")]
+pub async fn dota_voice_message(
+    bytes: Vec<u8>,
+    msg: &Message,
+    ctx_http: &Http,
+) -> anyhow::Result<()> {
+    use base64::prelude::{Engine as _, BASE64_STANDARD};
+    use serenity::all::MessageFlags;
+
+    use crate::transcode::VoiceMessage;
+
+    // decoding and encoding a clip takes long enough to stall the runtime
+    let voice = tokio::task::spawn_blocking(move || VoiceMessage::encode(&bytes)).await??;
+    let file_name = "voice-message.ogg";
+    // serenity's builders don't know about voice message attachments
+    let payload = serde_json::json!({
+        "flags": MessageFlags::IS_VOICE_MESSAGE.bits(),
+        "attachments": [{
+            "id": 0,
+            "filename": file_name,
+            "duration_secs": voice.duration.as_secs_f64(),
+            "waveform": BASE64_STANDARD.encode(&voice.waveform),
+        }],
+    });
+    let attachment = CreateAttachment::bytes(voice.ogg, file_name);
+    ctx_http
+        .send_message(msg.channel_id, vec![attachment], &payload)
+        .await?;
+    Ok(())
+}
+
+/// Follows up the embed in `msg` with a response's clip, the way the guild
+/// asked for
+pub async fn send_response_clip(
+    bytes: Option<Vec<u8>>,
+    res: &Response,
+    msg: &Message,
+    guild_id: Option<GuildId>,
+    ctx_http: &Http,
+) {
+    let mode = DATA
+        .get()
+        .unwrap()
+        .lock()
+        .unwrap()
+        .guild_settings(guild_id)
+        .clips;
+    match (mode, bytes) {
+        (ClipMode::Off, _) => {}
+        (ClipMode::Attachment, Some(bytes)) => {
+            let file_name = format!("{}.{}", &res.original_text, res.file_extension(&bytes));
+            let message = CreateMessage::new().add_file(CreateAttachment::bytes(bytes, file_name));
+            if let Err(e) = msg.channel_id.send_message(ctx_http, message).await {
+                tracing::error!("Error sending response audio: {:?}", e);
+            }
+        }
+        #[cfg(feature = "voice-messages")]
+        (ClipMode::VoiceMessage, Some(bytes)) => {
+            if let Err(e) = dota_voice_message(bytes.clone(), msg, ctx_http).await {
+                tracing::warn!("Falling back to a thread, voice message failed: {:?}", e);
+                dota_response_thread(Some(bytes), res, msg, ctx_http).await;
+            }
+        }
+        // without the clip, or a way to send it as asked, the thread still
+        // has the link
+        (_, bytes) => dota_response_thread(bytes, res, msg, ctx_http).await,
     }
 }
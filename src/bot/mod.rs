@@ -1,27 +1,43 @@
 mod commands;
 mod dota;
+#[cfg(feature = "voice")]
+mod voice;
 
 use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
 
 use poise::serenity_prelude as serenity;
 
 use serenity::{
-    all::{GatewayIntents, Message},
+    all::{Channel, ChannelId, GatewayIntents, GuildId, Message, UserId},
     async_trait,
-    builder::CreateMessage,
+    builder::{CreateAllowedMentions, CreateAttachment, CreateMessage},
     client::{Context, EventHandler},
     Client,
 };
 
-use crate::bot::{commands::*, dota::dota_response_thread};
+use crate::bot::{commands::*, dota::send_response_clip};
+use commands::cache::*;
+use commands::config::*;
+use commands::custom::*;
 use commands::dota::*;
+#[cfg(feature = "voice")]
+use commands::voice::*;
+#[cfg(feature = "voice")]
+use songbird::SerenityInit as _;
 
-use crate::response::Response;
-use crate::BOT_NAMES;
+use crate::cooldown::CooldownTracker;
+use crate::guild::{self, CustomReply, CustomResponse, GuildSettings};
+use crate::response::{Response, ResponseFilter};
+use crate::{BOT_NAMES, MIN_TRIGGER_WORDS};
 use crate::{process_text, DATA};
 
-static DOTA_COOLDOWN: OnceLock<Mutex<i32>> = OnceLock::new();
-const MIN_MESSAGES: i32 = 5;
+/// When each channel last got a triggered response
+static CHANNEL_COOLDOWNS: OnceLock<Mutex<CooldownTracker<ChannelId>>> = OnceLock::new();
+/// A member of a guild, or a user in direct messages
+type Member = (Option<GuildId>, UserId);
+/// When each member last triggered a response
+static USER_COOLDOWNS: OnceLock<Mutex<CooldownTracker<Member>>> = OnceLock::new();
 
 pub struct Bot {}
 struct Data {}
@@ -32,14 +48,41 @@ impl Bot {
     }
 
     pub async fn start(&mut self) {
-        let _ = DOTA_COOLDOWN.set(Mutex::new(0));
         dotenv::dotenv().ok();
         let token = std::env::var("DISCORD_TOKEN")
             .expect("Expected a token in the environment");
-        let intents = GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+        // guilds fill the cache of channels, to find a channel's category
+        let intents = GatewayIntents::GUILD_MESSAGES
+            | GatewayIntents::MESSAGE_CONTENT
+            | GatewayIntents::GUILDS;
+        // voice states are needed to find the channel a member is in
+        #[cfg(feature = "voice")]
+        let intents = intents | GatewayIntents::GUILD_VOICE_STATES;
+
+        #[allow(unused_mut)]
+        let mut config = config();
+        // subcommands can't be switched off by a feature inside the macro
+        #[cfg(feature = "voice")]
+        config.subcommands.push(config_volume());
+
+        #[allow(unused_mut)]
+        let mut commands = vec![
+            copypasta(),
+            help(),
+            disable(),
+            enable(),
+            dota(),
+            say(),
+            custom(),
+            config,
+            prefetch(),
+            verify_links(),
+        ];
+        #[cfg(feature = "voice")]
+        commands.extend([play(), queue(), skip(), stop()]);
 
         let poise_options = poise::FrameworkOptions {
-            commands: vec![copypasta(), help(), disable(), enable()],
+            commands,
             ..Default::default()
         };
 
@@ -58,11 +101,12 @@ impl Bot {
             .options(poise_options)
             .build();
 
-        let mut client = Client::builder(&token, intents)
+        let builder = Client::builder(&token, intents)
             .event_handler(Handler)
-            .framework(framework)
-            .await
-            .expect("Error creating client");
+            .framework(framework);
+        #[cfg(feature = "voice")]
+        let builder = builder.register_songbird();
+        let mut client = builder.await.expect("Error creating client");
 
         if let Err(e) = client.start().await {
             tracing::error!("Error starting clientr: {:?}", e);
@@ -85,20 +129,25 @@ impl EventHandler for Handler {
             return;
         }
 
-        if msg.content.split(' ').count() < 3 {
+        let settings = DATA.get().unwrap().lock().unwrap().guild_settings(msg.guild_id);
+        if !settings.triggers {
             return;
         }
-
-        // if let Some(mutex) = DOTA_COOLDOWN.get() {
-        //     let mut guard = mutex.lock().unwrap(); // just panic if poisoned
-        //     if *guard > 0 {
-        //         tracing::info!("Dota response is on cooldown...");
-        //         *guard -= 1;
-        //         return;
-        //     } else {
-        //         *guard = MIN_MESSAGES;
-        //     }
-        // }
+        if settings.has_channel_rules()
+            && !settings.channel_allowed(&channel_chain(&ctx, msg.channel_id).await)
+        {
+            return;
+        }
+        CHANNEL_COOLDOWNS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap()
+            .message(&msg.channel_id);
+        USER_COOLDOWNS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap()
+            .message(&(msg.guild_id, msg.author.id));
 
         if crate::DATA
             .get()
@@ -111,22 +160,39 @@ impl EventHandler for Handler {
             return;
         }
 
-        let res = self.get_response(&msg.content);
+        // a guild's own triggers win over the built-in lines, whatever their length
+        if let Some(custom) = self.get_custom_response(&msg.content, msg.guild_id) {
+            tracing::debug!("Custom response found: {:?}", custom);
+            if self.take_cooldown(&msg, &settings) {
+                self.send_custom_response(&ctx, &msg, &custom).await;
+            }
+            return;
+        }
+
+        // VGS codes are a single word, so check them before the length cutoff
+        if let Some(res) = self.get_vgs_response(&msg.content, msg.guild_id) {
+            tracing::debug!("VGS response found: {:?}", res);
+            if self.take_cooldown(&msg, &settings) {
+                self.send_response(&ctx, &msg, &res).await;
+            }
+            return;
+        }
+
+        if msg.content.split(' ').count() < MIN_TRIGGER_WORDS {
+            return;
+        }
+
+        let res = self.get_response(&msg.content, msg.guild_id);
         if let Some(res) = res {
             tracing::debug!("Response found: {:?}", res);
-            let bytes: Vec<u8> = reqwest::get(&res.response_link)
-                .await
-                .unwrap()
-                .bytes()
-                .await
-                .unwrap()
-                .to_vec();
-            let embed = dota::character_response_embed(res.hero_id);
-            let message = CreateMessage::new().add_embed(embed);
-            if let Ok(msg) = msg.channel_id.send_message(&ctx.http, message).await {
-                dota_response_thread(bytes, &res, &msg, &ctx.http).await;
-            } else {
-                tracing::error!("Error sending message");
+            if !self.take_cooldown(&msg, &settings) {
+                return;
+            }
+            self.send_response(&ctx, &msg, &res).await;
+            // turn rivalries into a little back and forth
+            if let Some(reply) = self.get_reply(&res, msg.guild_id) {
+                tracing::debug!("Reply found: {:?}", reply);
+                self.send_response(&ctx, &msg, &reply).await;
             }
         } else {
             tracing::info!("No response found for: {}", msg.content);
@@ -135,13 +201,138 @@ impl EventHandler for Handler {
 }
 
 impl Handler {
-    pub fn get_response(&self, text: &str) -> Option<Response> {
+    /// Whether both the message's channel and its author are off cooldown,
+    /// starting both cooldowns over when they are. Only triggered responses
+    /// go through here, commands bypass cooldowns
+    fn take_cooldown(&self, msg: &Message, settings: &GuildSettings) -> bool {
+        let mut channels = CHANNEL_COOLDOWNS.get_or_init(Default::default).lock().unwrap();
+        let mut users = USER_COOLDOWNS.get_or_init(Default::default).lock().unwrap();
+        let user = (msg.guild_id, msg.author.id);
+        let now = Instant::now();
+        if !channels.ready(&msg.channel_id, settings.cooldown, now) {
+            tracing::info!("Responses are on cooldown in {}", msg.channel_id);
+            return false;
+        }
+        if !users.ready(&user, settings.user_cooldown, now) {
+            tracing::info!("Responses are on cooldown for {}", msg.author.id);
+            return false;
+        }
+        channels.responded(msg.channel_id, now);
+        users.responded(user, now);
+        true
+    }
+
+    pub fn get_response(&self, text: &str, guild_id: Option<GuildId>) -> Option<Response> {
         let data = DATA.get().unwrap().lock().unwrap();
         let processed_text = process_text(text);
         data
-            .get_response(&processed_text, None)
+            .get_guild_response(&processed_text, guild_id)
+            .cloned()
+    }
+
+    pub fn get_custom_response(
+        &self,
+        text: &str,
+        guild_id: Option<GuildId>,
+    ) -> Option<CustomResponse> {
+        let data = DATA.get().unwrap().lock().unwrap();
+        data.guilds
+            .get(&guild_id?.to_string())?
+            .custom_response(&process_text(text))
+            .cloned()
+    }
+
+    pub fn get_vgs_response(&self, text: &str, guild_id: Option<GuildId>) -> Option<Response> {
+        let code = crate::parsing::vgs_code(text)?;
+        let data = DATA.get().unwrap().lock().unwrap();
+        // VGS codes work in any language
+        let filter = ResponseFilter {
+            language: None,
+            ..data.guild_filter(guild_id, ResponseFilter::default())?
+        };
+        data.response_database
+            .get_vgs_response(&code, &filter)
             .cloned()
     }
+
+    pub fn get_reply(&self, res: &Response, guild_id: Option<GuildId>) -> Option<Response> {
+        let data = DATA.get().unwrap().lock().unwrap();
+        data.get_guild_reply(res, guild_id).cloned()
+    }
+
+    async fn send_response(&self, ctx: &Context, msg: &Message, res: &Response) {
+        let bytes = dota::fetch_audio(res).await;
+        #[cfg(feature = "voice")]
+        if let (Some(guild_id), Some(bytes)) = (msg.guild_id, &bytes) {
+            // also play the line for an author sitting in a voice channel
+            if let Some(channel_id) = voice::member_channel(ctx, guild_id, msg.author.id) {
+                if let Err(e) = voice::play(ctx, guild_id, channel_id, res, bytes.clone()).await {
+                    tracing::warn!("Could not play response in voice: {:?}", e);
+                }
+            }
+        }
+        let embed = dota::character_response_embed(res);
+        let message = CreateMessage::new().add_embed(embed);
+        if let Ok(sent) = msg.channel_id.send_message(&ctx.http, message).await {
+            send_response_clip(bytes, res, &sent, msg.guild_id, &ctx.http).await;
+        } else {
+            tracing::error!("Error sending message");
+        }
+    }
+
+    async fn send_custom_response(&self, ctx: &Context, msg: &Message, custom: &CustomResponse) {
+        let message = match &custom.reply {
+            CustomReply::Text(text) => CreateMessage::new()
+                .content(text)
+                .allowed_mentions(CreateAllowedMentions::new()),
+            CustomReply::Audio { file_name, hash } => match guild::load_custom_audio(hash).await {
+                Ok(bytes) => {
+                    CreateMessage::new().add_file(CreateAttachment::bytes(bytes, file_name.clone()))
+                }
+                Err(e) => {
+                    tracing::error!("Error loading custom audio: {:?}", e);
+                    return;
+                }
+            },
+            CustomReply::Response { file } => {
+                let res = DATA
+                    .get()
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .response_database
+                    .get_by_file(file)
+                    .cloned();
+                match res {
+                    Some(res) => self.send_response(ctx, msg, &res).await,
+                    None => tracing::warn!("Custom response points at a missing line: {}", file),
+                }
+                return;
+            }
+        };
+        if let Err(e) = msg.channel_id.send_message(&ctx.http, message).await {
+            tracing::error!("Error sending custom response: {:?}", e);
+        }
+    }
+}
+
+/// The id of a channel followed by those of its parents: a thread's channel
+/// and a channel's category
+pub(crate) async fn channel_chain(ctx: &Context, channel_id: ChannelId) -> Vec<String> {
+    let mut chain = vec![channel_id.to_string()];
+    let mut current = channel_id;
+    // a thread sits at most two levels below its category
+    for _ in 0..2 {
+        let Ok(Channel::Guild(channel)) = current.to_channel(ctx).await else {
+            break;
+        };
+        let Some(parent) = channel.parent_id else {
+            break;
+        };
+        chain.push(parent.to_string());
+        current = parent;
+    }
+    chain
 }
 
 /// Splits the contents of msg into chunks of 2000 characters
@@ -0,0 +1,202 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use poise::serenity_prelude as serenity;
+use serenity::{
+    all::{ChannelId, GuildId, UserId},
+    async_trait,
+    client::Context,
+};
+use songbird::{
+    input::Input, tracks::Track, Event, EventContext, EventHandler as VoiceEventHandler, Songbird,
+};
+
+use crate::response::Response;
+
+/// How often to check whether the bot has anything left to play
+const IDLE_CHECK: Duration = Duration::from_secs(60);
+
+/// Most clips a guild can have waiting, counting the one that is playing
+pub const MAX_QUEUE_LENGTH: usize = 10;
+
+/// The response behind a queued clip
+#[derive(Debug)]
+pub struct QueuedResponse {
+    pub response_id: i32,
+    pub hero: String,
+    pub text: String,
+}
+
+/// What became of a clip handed to [`play`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Queued {
+    /// How many clips are ahead of it, 0 when it plays straight away
+    At(usize),
+    /// The same response is already last in the queue
+    Duplicate,
+    /// The queue already holds [`MAX_QUEUE_LENGTH`] clips
+    Full,
+}
+
+/// The voice channel a member is sitting in, from the cache
+pub fn member_channel(ctx: &Context, guild_id: GuildId, user_id: UserId) -> Option<ChannelId> {
+    let guild = guild_id.to_guild_cached(&ctx.cache)?;
+    guild
+        .voice_states
+        .get(&user_id)
+        .and_then(|state| state.channel_id)
+}
+
+async fn manager(ctx: &Context) -> Result<Arc<Songbird>> {
+    songbird::get(ctx)
+        .await
+        .ok_or_else(|| anyhow!("Voice support was not registered with the client"))
+}
+
+/// Joins `channel_id` if needed and queues a response's clip to play there. A
+/// bot already playing in another channel of the guild stays where it is
+pub async fn play(
+    ctx: &Context,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    res: &Response,
+    bytes: Vec<u8>,
+) -> Result<Queued> {
+    let manager = manager(ctx).await?;
+    let call = match manager.get(guild_id) {
+        Some(call) => {
+            let (current, busy) = {
+                let call = call.lock().await;
+                (call.current_channel(), !call.queue().is_empty())
+            };
+            if current != Some(channel_id.into()) {
+                if busy {
+                    return Err(anyhow!("Already playing in another channel"));
+                }
+                manager.join(guild_id, channel_id).await?
+            } else {
+                call
+            }
+        }
+        None => {
+            let call = manager.join(guild_id, channel_id).await?;
+            let leaver = IdleLeaver {
+                manager: manager.clone(),
+                guild_id,
+                was_idle: AtomicBool::new(false),
+            };
+            call.lock()
+                .await
+                .add_global_event(Event::Periodic(IDLE_CHECK, None), leaver);
+            call
+        }
+    };
+
+    let mut call = call.lock().await;
+    let queue = call.queue().current_queue();
+    if queue
+        .last()
+        .is_some_and(|track| track.data::<QueuedResponse>().response_id == res.id)
+    {
+        return Ok(Queued::Duplicate);
+    }
+    if queue.len() >= MAX_QUEUE_LENGTH {
+        return Ok(Queued::Full);
+    }
+
+    let (hero, volume) = {
+        let data = crate::DATA.get().unwrap().lock().unwrap();
+        let hero = data.response_database.get_base_hero_name(res.hero_id);
+        (
+            hero.unwrap_or("Unknown").to_string(),
+            data.guild_settings(Some(guild_id)).volume(),
+        )
+    };
+    let queued = QueuedResponse {
+        response_id: res.id,
+        hero,
+        text: res.original_text.clone(),
+    };
+    let track = Track::new_with_data(Input::from(bytes), Arc::new(queued)).volume(volume);
+    call.enqueue(track).await;
+    Ok(Queued::At(queue.len()))
+}
+
+/// The clips waiting in a guild, starting with the one playing
+pub async fn queue(ctx: &Context, guild_id: GuildId) -> Result<Vec<Arc<QueuedResponse>>> {
+    let Some(call) = manager(ctx).await?.get(guild_id) else {
+        return Ok(vec![]);
+    };
+    let queue = call.lock().await.queue().current_queue();
+    Ok(queue
+        .iter()
+        .map(|track| track.data::<QueuedResponse>())
+        .collect())
+}
+
+/// Skips the clip that is playing, giving false if nothing was
+pub async fn skip(ctx: &Context, guild_id: GuildId) -> Result<bool> {
+    let Some(call) = manager(ctx).await?.get(guild_id) else {
+        return Ok(false);
+    };
+    let call = call.lock().await;
+    if call.queue().is_empty() {
+        return Ok(false);
+    }
+    call.queue().skip()?;
+    Ok(true)
+}
+
+/// Clears the queue and leaves the voice channel
+pub async fn stop(ctx: &Context, guild_id: GuildId) -> Result<()> {
+    let manager = manager(ctx).await?;
+    if let Some(call) = manager.get(guild_id) {
+        call.lock().await.queue().stop();
+        manager.remove(guild_id).await?;
+    }
+    Ok(())
+}
+
+/// Changes the volume of every queued clip
+pub async fn set_volume(ctx: &Context, guild_id: GuildId, volume: f32) -> Result<()> {
+    if let Some(call) = manager(ctx).await?.get(guild_id) {
+        for track in call.lock().await.queue().current_queue() {
+            // fails only for a clip that finished in the meantime
+            let _ = track.set_volume(volume);
+        }
+    }
+    Ok(())
+}
+
+/// Leaves the voice channel once nothing has played for a whole check
+struct IdleLeaver {
+    manager: Arc<Songbird>,
+    guild_id: GuildId,
+    was_idle: AtomicBool,
+}
+
+#[async_trait]
+impl VoiceEventHandler for IdleLeaver {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let call = self.manager.get(self.guild_id)?;
+        let idle = call.lock().await.queue().is_empty();
+        if !idle {
+            self.was_idle.store(false, Ordering::Relaxed);
+            return None;
+        }
+        if self.was_idle.swap(true, Ordering::Relaxed) {
+            // removing the call stops this handler's driver, so do it elsewhere
+            let manager = self.manager.clone();
+            let guild_id = self.guild_id;
+            tokio::spawn(async move {
+                if let Err(e) = manager.remove(guild_id).await {
+                    tracing::error!("Error leaving voice channel: {:?}", e);
+                }
+            });
+            return Some(Event::Cancel);
+        }
+        None
+    }
+}
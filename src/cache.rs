@@ -0,0 +1,303 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Context as _, Result};
+use futures::StreamExt as _;
+use sha2::{Digest as _, Sha256};
+
+use crate::audio::DownloadError;
+
+const INDEX_FILE: &str = "index.ron";
+const DEFAULT_DIR: &str = "audio_cache";
+const DEFAULT_MAX_MB: u64 = 512;
+
+static AUDIO_CACHE: OnceLock<AudioCache> = OnceLock::new();
+
+/// An on-disk cache of voice line files. Files are stored under the hash of
+/// their contents, so links that serve the same clip share one copy, and the
+/// least recently used files are evicted once the cache grows past its limit
+#[derive(Debug)]
+pub struct AudioCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    index: Mutex<CacheIndex>,
+}
+
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+struct CacheIndex {
+    /// The hash of the file each link served
+    links: HashMap<String, String>,
+    blobs: HashMap<String, Blob>,
+    /// Ticks on every use, to order blobs by when they were last used
+    clock: u64,
+    /// Sum of the blobs' sizes, kept up to date rather than added up again
+    #[serde(skip)]
+    size: u64,
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize)]
+struct Blob {
+    size: u64,
+    last_used: u64,
+}
+
+impl CacheIndex {
+    fn add_blob(&mut self, hash: &str, size: u64) {
+        if !self.blobs.contains_key(hash) {
+            self.blobs.insert(hash.to_string(), Blob { size, last_used: 0 });
+            self.size += size;
+        }
+    }
+
+    fn touch(&mut self, hash: &str) {
+        self.clock += 1;
+        if let Some(blob) = self.blobs.get_mut(hash) {
+            blob.last_used = self.clock;
+        }
+    }
+
+    fn remove_blob(&mut self, hash: &str) {
+        if let Some(blob) = self.blobs.remove(hash) {
+            self.size -= blob.size;
+        }
+        self.links.retain(|_, h| h != hash);
+    }
+
+    /// Drops the least recently used blobs, other than `keep`, until the cache
+    /// fits in `max_bytes`. Gives the hashes that were dropped
+    fn evict(&mut self, max_bytes: u64, keep: &str) -> Vec<String> {
+        let mut evicted = vec![];
+        while self.size > max_bytes {
+            let oldest = self
+                .blobs
+                .iter()
+                .filter(|(hash, _)| *hash != keep)
+                .min_by_key(|(_, blob)| blob.last_used)
+                .map(|(hash, _)| hash.clone());
+            let Some(oldest) = oldest else {
+                break;
+            };
+            self.remove_blob(&oldest);
+            evicted.push(oldest);
+        }
+        evicted
+    }
+}
+
+impl AudioCache {
+    /// Opens the cache in `dir`, creating it if needed
+    pub fn open(dir: impl Into<PathBuf>, max_bytes: u64) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create audio cache at {}", dir.display()))?;
+        let mut index: CacheIndex = match std::fs::read_to_string(dir.join(INDEX_FILE)) {
+            Ok(index) => ron::from_str(&index).unwrap_or_else(|e| {
+                tracing::warn!("Audio cache index is corrupt, starting over: {}", e);
+                CacheIndex::default()
+            }),
+            Err(_) => CacheIndex::default(),
+        };
+        // forget anything deleted from under us
+        let missing = index
+            .blobs
+            .keys()
+            .filter(|hash| !dir.join(hash).is_file())
+            .cloned()
+            .collect::<Vec<_>>();
+        index.size = index.blobs.values().map(|blob| blob.size).sum();
+        for hash in missing {
+            index.remove_blob(&hash);
+        }
+
+        Ok(Self {
+            dir,
+            max_bytes,
+            index: Mutex::new(index),
+        })
+    }
+
+    /// The cache shared by the bot, configured by the `AUDIO_CACHE_DIR` and
+    /// `AUDIO_CACHE_MB` environment variables
+    pub fn global() -> &'static AudioCache {
+        AUDIO_CACHE.get_or_init(|| {
+            let dir = std::env::var("AUDIO_CACHE_DIR").unwrap_or_else(|_| DEFAULT_DIR.to_string());
+            let max_mb = std::env::var("AUDIO_CACHE_MB")
+                .ok()
+                .and_then(|mb| mb.parse().ok())
+                .unwrap_or(DEFAULT_MAX_MB);
+            AudioCache::open(dir, max_mb * 1024 * 1024).expect("Failed to open the audio cache")
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Total size of the cached files, in bytes
+    pub fn size(&self) -> u64 {
+        self.index.lock().unwrap().size
+    }
+
+    pub fn max_size(&self) -> u64 {
+        self.max_bytes
+    }
+
+    pub fn contains(&self, url: &str) -> bool {
+        self.index.lock().unwrap().links.contains_key(url)
+    }
+
+    /// The cached file for a link, if there is one
+    pub async fn get(&self, url: &str) -> Option<Vec<u8>> {
+        let hash = self.index.lock().unwrap().links.get(url).cloned()?;
+        match tokio::fs::read(self.dir.join(&hash)).await {
+            Ok(bytes) => {
+                self.index.lock().unwrap().touch(&hash);
+                Some(bytes)
+            }
+            Err(e) => {
+                tracing::warn!("Cached audio for {} is unreadable: {}", url, e);
+                self.index.lock().unwrap().remove_blob(&hash);
+                None
+            }
+        }
+    }
+
+    /// Stores the file a link served, evicting older files to make room
+    pub async fn insert(&self, url: &str, bytes: &[u8]) -> Result<()> {
+        self.store(url, bytes, true).await?;
+        self.write_index().await
+    }
+
+    /// Writes the file a link served and adds it to the index. Without `evict`
+    /// a file that doesn't fit is dropped instead, and false given back
+    async fn store(&self, url: &str, bytes: &[u8], evict: bool) -> Result<bool> {
+        let size = bytes.len() as u64;
+        if size > self.max_bytes {
+            return Ok(false);
+        }
+        let hash = format!("{:x}", Sha256::digest(bytes));
+        let path = self.dir.join(&hash);
+        if !path.is_file() {
+            // write to the side first so a crash never leaves half a file
+            let partial = self.dir.join(format!("{hash}.{}.part", rand::random::<u32>()));
+            tokio::fs::write(&partial, bytes).await?;
+            tokio::fs::rename(&partial, &path).await?;
+        }
+
+        let (stored, evicted) = {
+            let mut index = self.index.lock().unwrap();
+            let fits = index.blobs.contains_key(&hash) || index.size + size <= self.max_bytes;
+            if fits || evict {
+                index.links.insert(url.to_string(), hash.clone());
+                index.add_blob(&hash, size);
+                index.touch(&hash);
+                (true, index.evict(self.max_bytes, &hash))
+            } else {
+                (false, vec![hash])
+            }
+        };
+        for hash in evicted {
+            if let Err(e) = tokio::fs::remove_file(self.dir.join(&hash)).await {
+                tracing::warn!("Failed to evict {} from the audio cache: {}", hash, e);
+            }
+        }
+        Ok(stored)
+    }
+
+    /// Gets the file for a link from the cache, downloading it on a miss
+    pub async fn fetch(
+        &self,
+        client: &crate::Client,
+        url: &str,
+    ) -> std::result::Result<Vec<u8>, DownloadError> {
+        if let Some(bytes) = self.get(url).await {
+            return Ok(bytes);
+        }
+        let bytes = crate::audio::download(client, url).await?;
+        if let Err(e) = self.insert(url, &bytes).await {
+            tracing::warn!("Failed to cache audio for {}: {:?}", url, e);
+        }
+        Ok(bytes)
+    }
+
+    /// Downloads every link that isn't cached yet, `concurrency` at a time,
+    /// until the next clip would push out one that is already cached
+    pub async fn prefetch(
+        &self,
+        client: &crate::Client,
+        urls: impl IntoIterator<Item = String>,
+        concurrency: usize,
+    ) -> PrefetchReport {
+        let urls = urls
+            .into_iter()
+            .filter(|url| !self.contains(url))
+            .collect::<HashSet<_>>();
+        let full = AtomicBool::new(false);
+        let full = &full;
+        let results = futures::stream::iter(urls)
+            .map(|url| async move {
+                if full.load(Ordering::Relaxed) {
+                    return None;
+                }
+                let bytes = match crate::audio::download(client, &url).await {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        tracing::warn!("Failed to prefetch {}: {:?}", url, e);
+                        return Some(false);
+                    }
+                };
+                match self.store(&url, &bytes, false).await {
+                    Ok(true) => Some(true),
+                    Ok(false) => {
+                        full.store(true, Ordering::Relaxed);
+                        None
+                    }
+                    Err(e) => {
+                        tracing::warn!("Failed to cache audio for {}: {:?}", url, e);
+                        Some(false)
+                    }
+                }
+            })
+            .buffer_unordered(concurrency)
+            .collect::<Vec<_>>()
+            .await;
+        if let Err(e) = self.write_index().await {
+            tracing::warn!("Failed to save the audio cache index: {:?}", e);
+        }
+
+        PrefetchReport {
+            fetched: results.iter().filter(|r| **r == Some(true)).count(),
+            failed: results.iter().filter(|r| **r == Some(false)).count(),
+            skipped: results.iter().filter(|r| r.is_none()).count(),
+        }
+    }
+
+    /// Writes the index out, so the cache survives a restart
+    pub fn save(&self) -> Result<()> {
+        let index = ron::to_string(&*self.index.lock().unwrap())?;
+        std::fs::write(self.dir.join(INDEX_FILE), index)?;
+        Ok(())
+    }
+
+    /// [`AudioCache::save`] without blocking the runtime
+    async fn write_index(&self) -> Result<()> {
+        let index = ron::to_string(&*self.index.lock().unwrap())?;
+        // several inserts can save at once, so each writes its own file
+        let partial = self.dir.join(format!("{INDEX_FILE}.{}.part", rand::random::<u32>()));
+        tokio::fs::write(&partial, index).await?;
+        tokio::fs::rename(&partial, self.dir.join(INDEX_FILE)).await?;
+        Ok(())
+    }
+}
+
+/// What a [`AudioCache::prefetch`] got done
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PrefetchReport {
+    pub fetched: usize,
+    pub failed: usize,
+    /// Links left alone because the cache filled up, so nothing already
+    /// cached had to make room
+    pub skipped: usize,
+}
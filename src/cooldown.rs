@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use crate::guild::Cooldown;
+
+/// Keeps track of how long ago, in messages and in time, something last
+/// triggered a response
+#[derive(Debug)]
+pub struct CooldownTracker<K> {
+    activity: HashMap<K, Activity>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Activity {
+    last_response: Instant,
+    /// Messages seen since the last response
+    messages: u32,
+}
+
+impl<K> Default for CooldownTracker<K> {
+    fn default() -> Self {
+        Self {
+            activity: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash> CooldownTracker<K> {
+    /// Counts a message towards `key`'s cooldown
+    pub fn message(&mut self, key: &K) {
+        if let Some(activity) = self.activity.get_mut(key) {
+            activity.messages = activity.messages.saturating_add(1);
+        }
+    }
+
+    /// Whether `key` is off cooldown at `now`, counting the message that would
+    /// trigger the response
+    pub fn ready(&self, key: &K, cooldown: Cooldown, now: Instant) -> bool {
+        let Some(activity) = self.activity.get(key) else {
+            return true;
+        };
+        activity.messages > cooldown.messages
+            && now.saturating_duration_since(activity.last_response)
+                >= Duration::from_secs(cooldown.seconds.into())
+    }
+
+    /// Starts `key`'s cooldown over after a response
+    pub fn responded(&mut self, key: K, now: Instant) {
+        self.activity.insert(
+            key,
+            Activity {
+                last_response: now,
+                messages: 0,
+            },
+        );
+    }
+}
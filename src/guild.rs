@@ -0,0 +1,242 @@
+use std::path::PathBuf;
+
+use anyhow::{Context as _, Result};
+use sha2::{Digest as _, Sha256};
+
+use crate::response::{Game, PageKind};
+
+/// Where clips uploaded for custom responses are kept when
+/// `CUSTOM_AUDIO_DIR` isn't set
+const DEFAULT_CUSTOM_AUDIO_DIR: &str = "custom_audio";
+
+/// Most custom responses a single guild can have
+pub const MAX_CUSTOM_RESPONSES: usize = 100;
+
+/// Settings a guild's admins can change for their own server
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct GuildSettings {
+    /// Whether messages can trigger responses at all. Commands work either way
+    pub triggers: bool,
+    /// Ids of channels, or categories, messages can trigger responses in.
+    /// Every channel that isn't denied when empty
+    #[serde(alias = "channels")]
+    pub allowed_channels: Vec<String>,
+    /// Ids of channels, or categories, messages never trigger responses in
+    pub denied_channels: Vec<String>,
+    /// How long a channel waits after a triggered response before the next
+    /// one. Commands are never held back
+    pub cooldown: Cooldown,
+    /// How long a member waits after triggering a response before they can
+    /// trigger the next one, counting only their own messages
+    pub user_cooldown: Cooldown,
+    /// How much of a message has to match a line
+    pub match_mode: MatchMode,
+    /// How a response's clip is posted
+    pub clips: ClipMode,
+    /// Preferred response language, ie `ru`. Lines in other languages are
+    /// still used when nothing in the preferred language matches
+    pub language: Option<String>,
+    /// Voice playback volume in percent, full volume when unset
+    pub volume: Option<u8>,
+    /// Trigger phrases the guild added itself, checked before the built-in
+    /// responses
+    pub custom_responses: Vec<CustomResponse>,
+    /// Only lines from these games, every game when empty
+    pub games: Vec<Game>,
+    /// Only lines from these heroes and their variants, by name since ids
+    /// change on every update. Every hero when empty
+    pub heroes: Vec<String>,
+    /// Never lines from these kinds of page, ie announcer packs
+    pub excluded_kinds: Vec<PageKind>,
+}
+
+impl Default for GuildSettings {
+    fn default() -> Self {
+        Self {
+            triggers: true,
+            allowed_channels: vec![],
+            denied_channels: vec![],
+            cooldown: Cooldown::default(),
+            user_cooldown: Cooldown::default(),
+            match_mode: MatchMode::default(),
+            clips: ClipMode::default(),
+            language: None,
+            volume: None,
+            custom_responses: vec![],
+            games: vec![],
+            heroes: vec![],
+            excluded_kinds: vec![],
+        }
+    }
+}
+
+/// How long to hold off before responding again. A response is allowed once
+/// both have passed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Cooldown {
+    /// Messages that have to be sent in between
+    pub messages: u32,
+    pub seconds: u32,
+}
+
+impl Cooldown {
+    pub fn is_none(&self) -> bool {
+        self.messages == 0 && self.seconds == 0
+    }
+}
+
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Deserialize,
+    serde::Serialize,
+    poise::ChoiceParameter,
+)]
+pub enum MatchMode {
+    /// The whole message has to be a line
+    #[default]
+    #[name = "Whole message"]
+    Exact,
+    /// A line anywhere in the message will do
+    #[name = "Anywhere in the message"]
+    Contains,
+}
+
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Deserialize,
+    serde::Serialize,
+    poise::ChoiceParameter,
+)]
+pub enum ClipMode {
+    /// In a thread under the response, along with its link
+    #[default]
+    #[name = "In a thread"]
+    Thread,
+    /// As a file attached right below the response
+    #[name = "As a file"]
+    Attachment,
+    /// As a voice message, needs the `voice-messages` feature
+    #[name = "As a voice message"]
+    VoiceMessage,
+    /// Not at all
+    #[name = "Don't post clips"]
+    Off,
+}
+
+impl GuildSettings {
+    pub fn has_channel_rules(&self) -> bool {
+        !self.allowed_channels.is_empty() || !self.denied_channels.is_empty()
+    }
+
+    /// Whether the bot should answer in a channel. `chain` is the channel's
+    /// id followed by its parents', ie a thread, its channel and the channel's
+    /// category. The rule for the closest of them wins
+    pub fn channel_allowed(&self, chain: &[String]) -> bool {
+        for id in chain {
+            if self.denied_channels.contains(id) {
+                return false;
+            }
+            if self.allowed_channels.contains(id) {
+                return true;
+            }
+        }
+        self.allowed_channels.is_empty()
+    }
+
+    /// The voice playback volume as a multiplier, where 1.0 is unchanged
+    pub fn volume(&self) -> f32 {
+        self.volume.map_or(1.0, |percent| percent as f32 / 100.0)
+    }
+
+    /// The custom response for some text that went through
+    /// [`crate::process_text`]
+    pub fn custom_response(&self, processed_text: &str) -> Option<&CustomResponse> {
+        self.custom_responses
+            .iter()
+            .find(|custom| custom.trigger == processed_text)
+    }
+}
+
+/// A trigger phrase a guild mapped to a reply of its own choosing
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct CustomResponse {
+    /// The phrase after [`crate::process_text`]
+    pub trigger: String,
+    pub reply: CustomReply,
+    /// Id of the member who added it
+    pub author: String,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum CustomReply {
+    Text(String),
+    /// An uploaded clip, stored by [`save_custom_audio`]
+    Audio {
+        file_name: String,
+        hash: String,
+    },
+    /// A line from the response database, by the wiki's name for its clip
+    /// since ids change on every update
+    Response {
+        file: String,
+    },
+}
+
+impl CustomReply {
+    /// What the reply is, for listing it
+    pub fn describe(&self) -> String {
+        match self {
+            CustomReply::Text(text) if text.chars().count() > 80 => {
+                format!("text: {}…", text.chars().take(80).collect::<String>())
+            }
+            CustomReply::Text(text) => format!("text: {}", text),
+            CustomReply::Audio { file_name, .. } => format!("audio: {}", file_name),
+            CustomReply::Response { file } => format!("response: {}", file),
+        }
+    }
+}
+
+/// The folder uploaded custom response clips are kept in, set by the
+/// `CUSTOM_AUDIO_DIR` environment variable
+pub fn custom_audio_dir() -> PathBuf {
+    std::env::var("CUSTOM_AUDIO_DIR")
+        .unwrap_or_else(|_| DEFAULT_CUSTOM_AUDIO_DIR.to_string())
+        .into()
+}
+
+/// Stores an uploaded clip under the hash of its contents, which is given back
+/// to find it again. Unlike the audio cache, nothing here is ever evicted
+pub async fn save_custom_audio(bytes: &[u8]) -> Result<String> {
+    let dir = custom_audio_dir();
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+    let hash = format!("{:x}", Sha256::digest(bytes));
+    tokio::fs::write(dir.join(&hash), bytes).await?;
+    Ok(hash)
+}
+
+pub async fn load_custom_audio(hash: &str) -> Result<Vec<u8>> {
+    let path = custom_audio_dir().join(hash);
+    tokio::fs::read(&path)
+        .await
+        .with_context(|| format!("Failed to read custom audio {}", path.display()))
+}
+
+/// Deletes a stored clip that no custom response uses any more
+pub async fn remove_custom_audio(hash: &str) -> Result<()> {
+    tokio::fs::remove_file(custom_audio_dir().join(hash)).await?;
+    Ok(())
+}
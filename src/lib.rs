@@ -1,23 +1,36 @@
-use std::{collections::HashMap, sync::Mutex};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
 
+pub mod audio;
 pub mod bot;
+pub mod cache;
+pub mod cooldown;
+pub mod guild;
 pub mod parsing;
 pub mod response;
 pub mod serde_response;
 pub mod tests;
+pub mod transcode;
 
-use rand::seq::IteratorRandom;
 use reqwest::{header::{HeaderMap, HeaderValue, USER_AGENT}, IntoUrl, Method};
 use reqwest_middleware::{ClientBuilder, RequestBuilder};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
 use std::time::Duration;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization as _};
 
-use crate::response::{Response, ResponseDatabase};
+use crate::guild::{GuildSettings, MatchMode};
+use crate::response::{Response, ResponseDatabase, ResponseFilter};
 
 pub static DATA: std::sync::OnceLock<Mutex<Data>> = std::sync::OnceLock::new();
 
 const BOT_NAMES: [&str; 2] = ["ShakeBot", "ShakeBotDev"];
 
+/// Fewest words a message, or the part of it that matched, needs to trigger a
+/// response. Shorter lines like "yes" come up in conversation too often
+pub const MIN_TRIGGER_WORDS: usize = 3;
+
 #[derive(Debug, Clone)]
 pub struct Client {
     client: reqwest_middleware::ClientWithMiddleware,
@@ -30,7 +43,7 @@ impl Default for Client {
                 "Mozilla/5.0 (compatible; WebScraper/1.0)"
         ));
         let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
-        return Self {
+        Self {
             client: ClientBuilder::new(
                 reqwest::Client::builder()
                     .pool_max_idle_per_host(10)
@@ -44,21 +57,36 @@ impl Default for Client {
             )
             .with(RetryTransientMiddleware::new_with_policy(retry_policy))
             .build(),
-        };
+        }
     }
 }
 
+static CLIENT: std::sync::OnceLock<Client> = std::sync::OnceLock::new();
+
 impl Client {
+    /// One client for the whole bot, so connections to the wikis get reused
+    pub fn shared() -> &'static Client {
+        CLIENT.get_or_init(Client::default)
+    }
+
     /// See [`Client::get`]
     pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
         self.client.request(Method::GET, url)
     }
+
+    /// See [`Client::head`]
+    pub fn head<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.request(Method::HEAD, url)
+    }
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct Data {
     pub response_database: ResponseDatabase,
     pub disabled_users: Vec<String>,
+    /// Per guild settings, keyed by guild id
+    #[serde(default)]
+    pub guilds: HashMap<String, GuildSettings>,
 }
 
 impl Default for Data {
@@ -68,8 +96,10 @@ impl Default for Data {
                 responses: vec![],
                 heroes: HashMap::new(),
                 icons: HashMap::new(),
+                dead_links: HashSet::new(),
             },
             disabled_users: vec![],
+            guilds: HashMap::new(),
         }
     }
 }
@@ -81,56 +111,153 @@ impl Data {
         self.response_database.heroes.clear();
         tracing::info!("Populating responses");
         self.response_database.populate_responses().await;
+        self.response_database.forget_repaired_links();
     }
 
-    pub fn get_response(&self, processed_text: &str, hero_id: Option<i32>) -> Option<&Response> {
-        match hero_id {
-            Some(id) => self
-                .response_database
-                .responses
-                .iter()
-                .filter(|r| r.processed_text == processed_text && r.hero_id == id)
-                .choose(&mut rand::thread_rng()),
-            None => self
-                .response_database
-                .responses
+    pub fn get_response(&self, processed_text: &str, filter: &ResponseFilter) -> Option<&Response> {
+        self.response_database.get_response(processed_text, filter)
+    }
+
+    /// The settings for a guild, or the defaults if it has never changed any
+    pub fn guild_settings(&self, guild_id: Option<impl ToString>) -> GuildSettings {
+        guild_id
+            .and_then(|id| self.guilds.get(&id.to_string()))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn guild_settings_mut(&mut self, guild_id: impl ToString) -> &mut GuildSettings {
+        self.guilds.entry(guild_id.to_string()).or_default()
+    }
+
+    /// Narrows `filter` down to the games, heroes and kinds of page a guild
+    /// has switched on and to its preferred language. Gives `None` when the
+    /// heroes asked for are all switched off
+    pub fn guild_filter(
+        &self,
+        guild_id: Option<impl ToString>,
+        mut filter: ResponseFilter,
+    ) -> Option<ResponseFilter> {
+        let settings = self.guild_settings(guild_id);
+        let db = &self.response_database;
+        if !settings.heroes.is_empty() {
+            let allowed = settings
+                .heroes
                 .iter()
-                .filter(|r| r.processed_text == processed_text)
-                .choose(&mut rand::thread_rng()),
+                .flat_map(|name| db.get_hero_family(name))
+                .collect::<Vec<_>>();
+            if filter.heroes.is_empty() {
+                filter.heroes = allowed;
+            } else {
+                filter.heroes.retain(|id| allowed.contains(id));
+                if filter.heroes.is_empty() {
+                    return None;
+                }
+            }
         }
+        filter.games = settings.games;
+        filter.excluded_kinds.extend(settings.excluded_kinds);
+        filter.language = settings.language;
+        Some(filter)
+    }
+
+    /// Looks up a response among the lines a guild has switched on, matching
+    /// the way the guild asked for, preferring lines in the guild's language
+    /// and falling back to any language
+    pub fn get_guild_response(
+        &self,
+        processed_text: &str,
+        guild_id: Option<impl ToString>,
+    ) -> Option<&Response> {
+        let guild_id = guild_id.map(|id| id.to_string());
+        let match_mode = self.guild_settings(guild_id.as_deref()).match_mode;
+        let filter = self.guild_filter(guild_id.as_deref(), ResponseFilter::default())?;
+        let find = |filter: &ResponseFilter| match match_mode {
+            MatchMode::Exact => self.get_response(processed_text, filter),
+            MatchMode::Contains => {
+                self.response_database
+                    .find_in_text(processed_text, MIN_TRIGGER_WORDS, filter)
+            }
+        };
+        find(&filter).or_else(|| {
+            let filter = ResponseFilter {
+                language: None,
+                ..filter
+            };
+            find(&filter)
+        })
+    }
+
+    /// [`ResponseDatabase::get_reply`] among the lines the guild has switched
+    /// on, preferring its language like [`Data::get_guild_response`]
+    pub fn get_guild_reply(
+        &self,
+        res: &Response,
+        guild_id: Option<impl ToString>,
+    ) -> Option<&Response> {
+        let filter = self.guild_filter(guild_id, ResponseFilter::default())?;
+        self.response_database.get_reply(res, &filter).or_else(|| {
+            let filter = ResponseFilter {
+                language: None,
+                ..filter
+            };
+            self.response_database.get_reply(res, &filter)
+        })
     }
 }
 
+/// The combining mark NFKD splits off й
+const BREVE: char = '\u{306}';
+
 /// Function for pre-processing the given response text.
 /// It:
-/// * converts all unicode characters to their nearest ASCII equivalent
-/// * replaces all punctuations with spaces
+/// * folds compatibility characters (ligatures, full-width letters, etc) into
+///   their plain equivalents
+/// * strips diacritics, so "Señor" matches "senor"
+/// * leaves letters from other scripts, like Cyrillic, in place. Russian й is
+///   a letter of its own rather than и with a mark, so it is kept
+/// * replaces all punctuations, symbols and emoji with spaces
 /// * replaces all whitespace characters (tab, newline etc) with spaces
 /// * removes trailing and leading spaces
 /// * removes double spaces
 /// * changes to lowercase
 pub fn process_text(text: &str) -> String {
-    let text = text
+    let mut stripped = String::with_capacity(text.len());
+    for c in text.nfkd() {
+        if !is_combining_mark(c) {
+            stripped.push(c);
+            continue;
+        }
+        // put й back together, every other mark goes
+        if c == BREVE && stripped.ends_with(['и', 'И']) {
+            let base = stripped.pop();
+            stripped.push(if base == Some('и') { 'й' } else { 'Й' });
+        }
+    }
+    stripped
         .chars()
-        .map(|c| match c {
-            '’' => '\'',
-            '“' | '”' => '"',
-            '–' => '-',
-            // '…' => '',
-            '—' => '-',
-            '‘' => '\'',
-            '•' => '-',
-            _ => c,
+        .flat_map(|c| {
+            // letters NFKD leaves alone but that have a common ASCII spelling
+            let folded = match c {
+                'Æ' | 'æ' => "ae",
+                'Œ' | 'œ' => "oe",
+                'ß' => "ss",
+                'Ø' | 'ø' => "o",
+                'Đ' | 'đ' => "d",
+                'Ł' | 'ł' => "l",
+                'Þ' | 'þ' => "th",
+                'ı' => "i",
+                _ => "",
+            };
+            match folded {
+                "" => vec![c],
+                _ => folded.chars().collect(),
+            }
         })
         .collect::<String>()
-        .replace('…', "...")
         .to_lowercase()
         .replace(|c: char| !c.is_alphanumeric(), " ")
         .split_whitespace()
         .collect::<Vec<&str>>()
         .join(" ")
-        .trim()
-        .to_string();
-
-    return text;
 }
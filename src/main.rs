@@ -7,13 +7,18 @@ use shake_bot::DATA;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    dotenv::dotenv().ok();
     let subscriber = tracing_subscriber::fmt()
         .with_max_level(tracing::Level::INFO)
         .finish();
     let _ = tracing::subscriber::set_global_default(subscriber);
 
     let data: Data = match std::fs::read_to_string("data.ron") {
-        Ok(d) => ron::from_str(&d)?,
+        Ok(d) => {
+            let mut data: Data = ron::from_str(&d)?;
+            data.response_database.reprocess_text();
+            data
+        }
         Err(_) => {
             tracing::info!("No data file found, creating a new one");
             let mut defaults = Data::default();
@@ -56,5 +61,6 @@ fn clean_up() -> Result<()> {
         .unwrap();
     let string = ron::to_string(&*data)?;
     std::fs::write("data.ron", string).unwrap();
+    shake_bot::cache::AudioCache::global().save()?;
     Ok(())
 }
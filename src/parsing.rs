@@ -1,28 +1,321 @@
 use winnow::{
-    combinator::{alt, delimited, seq},
-    error::{AddContext as _, ContextError, ErrMode, StrContext},
+    combinator::{alt, delimited, opt, seq},
+    error::StrContext,
     prelude::*,
-    token::{literal, take, take_until, take_while},
+    token::{literal, rest, take, take_till, take_until, take_while},
 };
 
 use regex::Regex;
 
-#[derive(Debug, PartialEq, Clone)]
+/// Wiki headings only go down to `======`, so italic sub-headings always sit
+/// below every `=` heading
+const SUBHEADING_LEVEL: usize = 7;
+
+#[derive(Debug, PartialEq, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Response {
     pub file: String,
+    /// The line as plain text, without any markup
     pub response: String,
+    /// The line with its emphasis, stage directions and asides
+    #[serde(default)]
+    pub rich_text: RichText,
+    /// The headings this line appeared under, outermost first
+    pub category: Vec<String>,
+    pub meta: ResponseMeta,
+    /// The Smite VGS key sequence that plays this line, ie `VVGG`
+    pub vgs: Option<String>,
+}
+
+impl Response {
+    fn new(file: &str, rich_text: RichText, meta: ResponseMeta) -> Self {
+        Self {
+            file: file_title(file),
+            response: rich_text.plain(),
+            rich_text,
+            category: vec![],
+            meta,
+            vgs: None,
+        }
+    }
+
+    fn with_vgs(mut self, vgs: Option<String>) -> Self {
+        self.vgs = vgs;
+        self
+    }
+}
+
+/// Turns a file name into the title the wiki API knows it by, ie
+/// `vo_abaddon_spawn_01.mp3` becomes `Vo abaddon spawn 01.mp3`
+fn file_title(file: &str) -> String {
+    let file = file.replace('_', " ");
+    let mut chars = file.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+        None => file,
+    }
+}
+
+/// Reads a Smite VGS key sequence such as `VVGG` or `'''vee'''`, returning it
+/// in upper case. Anything else, like a table cell's `align="left"`, is `None`
+pub fn vgs_code(text: &str) -> Option<String> {
+    let code = text.trim().trim_matches('\'').trim();
+    let is_code = (2..=5).contains(&code.len())
+        && code.chars().all(|c| c.is_ascii_alphabetic())
+        && code.starts_with(['v', 'V']);
+    is_code.then(|| code.to_ascii_uppercase())
+}
+
+/// How often the game plays a line, from `{{resp|r}}` and `{{resp|u}}`
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, serde::Deserialize, serde::Serialize,
+)]
+pub enum Rarity {
+    Rare,
+    UltraRare,
+}
+
+/// Information carried by the templates and icons around a line
+#[derive(Debug, Default, PartialEq, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct ResponseMeta {
+    pub rarity: Option<Rarity>,
+    /// Seconds before the line can play again, from `{{resp|60}}`
+    pub cooldown: Option<u32>,
+    /// Any other `{{resp|...}}` flags, kept verbatim
+    pub conditions: Vec<String>,
+    /// The hero named by a `{{Hero icon|...}}` tag anywhere on the line
+    pub related_hero: Option<String>,
+    /// The hero a line is directed at, from a leading `{{Hero icon|...}}` tag
+    pub target_hero: Option<String>,
+    /// The ability or item named by an icon tag
+    pub related_ability: Option<String>,
+}
+
+impl ResponseMeta {
+    /// Folds a `{{...}}` template into the metadata, `leading` is true when no
+    /// text has been seen yet on the line
+    fn apply_template(&mut self, template: &str, leading: bool) {
+        let mut args = template.split('|').map(str::trim);
+        let name = args.next().unwrap_or_default().to_lowercase();
+        match name.as_str() {
+            "resp" => {
+                for arg in args {
+                    match arg {
+                        "r" => self.rarity = Some(Rarity::Rare),
+                        "u" => self.rarity = Some(Rarity::UltraRare),
+                        _ => match arg.parse::<u32>() {
+                            Ok(seconds) => self.cooldown = Some(seconds),
+                            Err(_) => self.conditions.push(arg.to_string()),
+                        },
+                    }
+                }
+            }
+            "hero icon" => {
+                self.related_hero = args.next().map(str::to_string);
+                if leading {
+                    self.target_hero = self.related_hero.clone();
+                }
+            }
+            "ability icon" | "item icon" => {
+                self.related_ability = args.next().map(str::to_string)
+            }
+            _ => (),
+        }
+    }
+}
+
+/// A run of a line's text along with how the wiki formats it
+#[derive(Debug, PartialEq, Clone, serde::Deserialize, serde::Serialize)]
+pub enum Span {
+    Plain(String),
+    Italic(String),
+    Bold(String),
+    BoldItalic(String),
+    /// A sound or action rather than speech, ie `[laughs]`
+    StageDirection(String),
+    /// An aside from a `<small>` tag, ie `(Low health)`. It is shown with the
+    /// line but is not part of what is said
+    Annotation(String),
+}
+
+/// A response line split into formatted runs
+#[derive(Debug, Default, PartialEq, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(transparent)]
+pub struct RichText(pub Vec<Span>);
+
+impl RichText {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn push(&mut self, span: Span) {
+        use Span::*;
+        match (self.0.last_mut(), span) {
+            (Some(Plain(last)), Plain(text))
+            | (Some(Italic(last)), Italic(text))
+            | (Some(Bold(last)), Bold(text))
+            | (Some(BoldItalic(last)), BoldItalic(text)) => last.push_str(&text),
+            (_, span) => self.0.push(span),
+        }
+    }
+
+    fn push_text(&mut self, text: &str, emphasis: Emphasis) {
+        if text.is_empty() {
+            return;
+        }
+        let text = text.to_string();
+        self.push(match (emphasis.italic, emphasis.bold) {
+            (false, false) => Span::Plain(text),
+            (true, false) => Span::Italic(text),
+            (false, true) => Span::Bold(text),
+            (true, true) => Span::BoldItalic(text),
+        });
+    }
+
+    /// The words of the line, which is what gets matched against. Stage
+    /// directions are kept without their brackets and asides are left out
+    pub fn plain(&self) -> String {
+        self.0
+            .iter()
+            .filter_map(|span| match span {
+                Span::Plain(text)
+                | Span::Italic(text)
+                | Span::Bold(text)
+                | Span::BoldItalic(text)
+                | Span::StageDirection(text) => Some(text.as_str()),
+                Span::Annotation(_) => None,
+            })
+            .collect()
+    }
+
+    /// The line formatted with Discord markdown
+    pub fn markdown(&self) -> String {
+        let mut markdown = String::new();
+        for span in &self.0 {
+            match span {
+                Span::Plain(text) => markdown.push_str(&escape_markdown(text)),
+                Span::Italic(text) => markdown.push_str(&wrap_markdown(text, "*")),
+                Span::Bold(text) => markdown.push_str(&wrap_markdown(text, "**")),
+                Span::BoldItalic(text) => markdown.push_str(&wrap_markdown(text, "***")),
+                Span::StageDirection(text) => {
+                    markdown.push_str(&format!("*[{}]*", escape_markdown(text.trim())))
+                }
+                Span::Annotation(text) => {
+                    // asides swallow the space after them in the source
+                    markdown.push_str(&format!("*{}* ", escape_markdown(text.trim())))
+                }
+            }
+        }
+        markdown.trim_end().to_string()
+    }
+}
+
+/// Backslash escapes anything Discord would read as markdown
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if ['\\', '*', '_', '~', '`', '|', '>', '#'].contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Surrounds text with a markdown marker, keeping any outer whitespace outside
+/// of it since Discord won't format `** bold **`
+fn wrap_markdown(text: &str, marker: &str) -> String {
+    let inner = text.trim();
+    if inner.is_empty() {
+        return text.to_string();
+    }
+    let start = text.len() - text.trim_start().len();
+    let end = start + inner.len();
+    format!(
+        "{}{marker}{}{marker}{}",
+        &text[..start],
+        escape_markdown(inner),
+        &text[end..]
+    )
+}
+
+/// The emphasis in effect at some point of a line
+#[derive(Debug, Default, Clone, Copy)]
+struct Emphasis {
+    italic: bool,
+    bold: bool,
+}
+
+impl Emphasis {
+    /// Applies a run of apostrophes the way MediaWiki does, returning how many
+    /// of them are literal text rather than markup
+    fn toggle(&mut self, quotes: usize) -> usize {
+        match quotes {
+            2 => self.italic = !self.italic,
+            3 => self.bold = !self.bold,
+            4 => {
+                self.bold = !self.bold;
+                return 1;
+            }
+            n => {
+                self.italic = !self.italic;
+                self.bold = !self.bold;
+                return n - 5;
+            }
+        }
+        0
+    }
+}
+
+/// A line that was skipped because it could not be parsed
+#[derive(Debug, PartialEq, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Diagnostic {
+    /// 1-based line number in the page source
+    pub line: usize,
+    pub text: String,
+    /// The winnow error, including its context labels
+    pub error: String,
+}
+
+/// Everything recovered from a page, along with the lines that were skipped
+#[derive(Debug, Default, PartialEq, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ParsedPage {
+    pub responses: Vec<Response>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Tracks the heading hierarchy while walking a page
+#[derive(Debug, Default)]
+struct Sections {
+    headings: Vec<(usize, String)>,
+}
+
+impl Sections {
+    fn enter(&mut self, level: usize, title: String) {
+        self.headings.retain(|(l, _)| *l < level);
+        if !title.is_empty() {
+            self.headings.push((level, title));
+        }
+    }
+
+    fn current(&self) -> Vec<String> {
+        self.headings.iter().map(|(_, title)| title.clone()).collect()
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 enum SquareTag<'a> {
     Ignorable,
     ContainsResponse(&'a str),
+    /// A file tag linking to an ability or item, ie [[File:x.png|16px|link=Mist Coil]]
+    Icon(&'a str),
 }
 
 #[derive(Debug, PartialEq, Clone)]
-enum ResponseKind {
+enum ResponseKind<'a> {
     Standard,
-    Vgs,
+    /// Holds the first cell of the row, which may be the VGS key sequence
+    Vgs(&'a str),
 }
 
 fn parse_vertical_bar<'a>(input: &mut &'a str) -> ModalResult<&'a str> {
@@ -31,14 +324,8 @@ fn parse_vertical_bar<'a>(input: &mut &'a str) -> ModalResult<&'a str> {
         .parse_next(input)
 }
 
-fn parse_new_line<'a>(input: &mut &'a str) -> ModalResult<&'a str> {
-    literal("\n")
-        .context(StrContext::Label("newline"))
-        .parse_next(input)
-}
-
 fn parse_squirly_tag<'a>(input: &mut &'a str) -> ModalResult<&'a str> {
-    // always ignored
+    // never part of the response, but may carry metadata
     let capture = delimited(r"{{", take_until(0.., r"}}"), r"}}")
         .context(StrContext::Label("squirly delimiter"))
         .parse_next(input)?;
@@ -54,7 +341,13 @@ fn parse_square_tag<'a>(input: &mut &'a str) -> ModalResult<SquareTag<'a>> {
         .parse_next(input)?;
     if capture.contains('|') {
         ignore_space.parse_next(input)?;
-        Ok(SquareTag::Ignorable)
+        let link = capture
+            .split('|')
+            .find_map(|arg| arg.trim().strip_prefix("link="));
+        match link {
+            Some(link) if !link.is_empty() => Ok(SquareTag::Icon(link)),
+            _ => Ok(SquareTag::Ignorable),
+        }
     } else {
         Ok(SquareTag::ContainsResponse(capture))
     }
@@ -99,11 +392,100 @@ fn parse_angle_tag<'a>(input: &mut &'a str) -> ModalResult<&'a str> {
 }
 
 fn parse_quote<'a>(input: &mut &'a str) -> ModalResult<&'a str> {
-    take_while(0.., |c: char| !['{', '[', '<', '\n', '\r'].contains(&c))
-        .context(StrContext::Label("quote"))
+    // a lone `{`, `'` or a line break can't start markup, so take it as text
+    // rather than getting stuck on it
+    alt((
+        take_while(1.., |c: char| !['{', '[', '<', '\'', '\n', '\r'].contains(&c)),
+        take(1usize),
+    ))
+    .context(StrContext::Label("quote"))
+    .parse_next(input)
+}
+
+/// An html tag that turns emphasis on or off, ie `<i>` or `</b>`. Gives the
+/// emphasis it names and whether it is turned on
+fn parse_emphasis_tag(input: &mut &str) -> ModalResult<(Emphasis, bool)> {
+    delimited(r"<", take_until(0.., r">"), r">")
+        .verify_map(|tag: &str| {
+            let (name, on) = match tag.trim().strip_prefix('/') {
+                Some(name) => (name, false),
+                None => (tag, true),
+            };
+            let emphasis = match name.trim().to_lowercase().as_str() {
+                "i" | "em" => Emphasis { italic: true, bold: false },
+                "b" | "strong" => Emphasis { italic: false, bold: true },
+                _ => return None,
+            };
+            Some((emphasis, on))
+        })
+        .context(StrContext::Label("emphasis tag"))
         .parse_next(input)
 }
 
+fn parse_annotation<'a>(input: &mut &'a str) -> ModalResult<&'a str> {
+    // of the form <small>(Low health)</small>
+    let capture = delimited(
+        delimited(r"<", take_until(0.., r">"), r">").verify(|tag: &str| {
+            tag.split_whitespace()
+                .next()
+                .is_some_and(|name| name.eq_ignore_ascii_case("small"))
+        }),
+        take_until(0.., r"</"),
+        delimited(r"</", take_until(0.., r">"), r">"),
+    )
+    .context(StrContext::Label("annotation"))
+    .parse_next(input)?;
+    ignore_space.parse_next(input)?;
+    Ok(capture)
+}
+
+fn parse_heading<'a>(input: &mut &'a str) -> ModalResult<(usize, &'a str)> {
+    // of the form == Title ==
+    let level = take_while(2..=6, '=')
+        .context(StrContext::Label("heading open"))
+        .parse_next(input)?
+        .len();
+    // the title itself may hold an `=`, ie [[File:x.png|link=Mist Coil]]
+    let title = rest
+        .verify_map(|line: &'a str| {
+            let title = line.trim_end_matches('=');
+            (line.len() - title.len() == level && !title.is_empty()).then_some(title)
+        })
+        .context(StrContext::Label("heading close"))
+        .parse_next(input)?;
+    Ok((level, title))
+}
+
+fn parse_italic_heading<'a>(input: &mut &'a str) -> ModalResult<&'a str> {
+    // of the form ''Title'', alone on its line
+    delimited(
+        take_while(2..=3, '\''),
+        take_till(1.., '\''),
+        take_while(2..=3, '\''),
+    )
+    .context(StrContext::Label("italic heading"))
+    .parse_next(input)
+}
+
+/// Cleans any markup out of a fragment such as a heading title
+fn markup_text(fragment: &str) -> String {
+    let mut fragment = fragment;
+    parse_response(&mut fragment)
+        .unwrap_or_else(|_| fragment.to_string())
+        .trim()
+        .to_string()
+}
+
+/// Reads the markup of a fragment that was cut out of a table cell, falling
+/// back to the fragment as is. The cell's padding is left out
+fn rich_text(fragment: &str) -> RichText {
+    let fragment = fragment.trim();
+    let mut input = fragment;
+    parse_rich_response(&mut input)
+        .map(|(text, _)| text)
+        .unwrap_or_else(|_| RichText(vec![Span::Plain(fragment.to_string())]))
+}
+
 fn ignore_space<'a>(input: &mut &'a str) -> ModalResult<&'a str> {
     take_while(0.., ' ')
         .context(StrContext::Label("ignore space"))
@@ -111,39 +493,75 @@ fn ignore_space<'a>(input: &mut &'a str) -> ModalResult<&'a str> {
 }
 
 pub fn parse_response(input: &mut &str) -> ModalResult<String> {
+    parse_response_with_meta
+        .map(|(response, _)| response)
+        .parse_next(input)
+}
+
+/// Like [`parse_response`], but also collects the metadata from any templates
+/// and icons in the line
+pub fn parse_response_with_meta(input: &mut &str) -> ModalResult<(String, ResponseMeta)> {
+    parse_rich_response
+        .map(|(text, meta)| (text.plain(), meta))
+        .parse_next(input)
+}
+
+/// Like [`parse_response_with_meta`], but keeps the line's formatting
+pub fn parse_rich_response(input: &mut &str) -> ModalResult<(RichText, ResponseMeta)> {
     ignore_space.parse_next(input)?;
-    let mut response = String::new();
+    let mut text = RichText::default();
+    let mut meta = ResponseMeta::default();
+    let mut emphasis = Emphasis::default();
     while !input.is_empty() {
         if input.starts_with("{{") {
-            parse_squirly_tag.parse_next(input)?;
+            let template = parse_squirly_tag.parse_next(input)?;
+            meta.apply_template(template, text.plain().trim().is_empty());
         } else if input.starts_with("[[") {
             let tag = parse_square_tag.parse_next(input)?;
             match tag {
                 SquareTag::Ignorable => (),
-                SquareTag::ContainsResponse(quote_fragment) => response.push_str(quote_fragment),
+                SquareTag::ContainsResponse(quote_fragment) => {
+                    text.push_text(quote_fragment, emphasis)
+                }
+                SquareTag::Icon(link) => meta.related_ability = Some(link.to_string()),
             }
         } else if input.starts_with('[') {
             let tag = parse_single_square_tag.parse_next(input)?;
-            response.push_str(tag);
+            text.push(Span::StageDirection(tag.to_string()));
+        } else if input.starts_with("''") {
+            let quotes = take_while(2.., '\'').parse_next(input)?;
+            let literal = emphasis.toggle(quotes.len());
+            text.push_text(&quotes[..literal], emphasis);
         } else if input.starts_with('<') {
-            parse_angle_tag.parse_next(input)?;
+            if let Some((tag, on)) = opt(parse_emphasis_tag).parse_next(input)? {
+                if tag.italic {
+                    emphasis.italic = on;
+                }
+                if tag.bold {
+                    emphasis.bold = on;
+                }
+            } else if let Some(aside) = opt(parse_annotation).parse_next(input)? {
+                text.push(Span::Annotation(markup_text(aside)));
+            } else {
+                parse_angle_tag.parse_next(input)?;
+            }
         } else {
             let quote_fragment = parse_quote.parse_next(input)?;
-            response.push_str(quote_fragment);
+            text.push_text(quote_fragment, emphasis);
         }
     }
 
-    Ok(response)
+    Ok((text, meta))
 }
 
-fn parse_begin_line<'a>(input: &mut &'a str) -> ModalResult<ResponseKind> {
+fn parse_begin_line<'a>(input: &mut &'a str) -> ModalResult<ResponseKind<'a>> {
     if input.starts_with('*') {
         take_until(0.., r"<")
             .context(StrContext::Label("begin line"))
             .parse_next(input)?;
         Ok(ResponseKind::Standard)
     } else {
-        seq!(
+        let (_, cell, _, _) = seq!(
             parse_vertical_bar,
             take_until(0.., '|'),
             parse_vertical_bar,
@@ -151,26 +569,25 @@ fn parse_begin_line<'a>(input: &mut &'a str) -> ModalResult<ResponseKind> {
         )
         .context(StrContext::Label("begin line"))
         .parse_next(input)?;
-        Ok(ResponseKind::Vgs)
+        Ok(ResponseKind::Vgs(cell))
     }
 }
 
-pub fn parse_response_line(input: &mut &str) -> ModalResult<Vec<(String, String)>> {
+/// Parses a single line into its responses, some lines hold more than one.
+/// The category is left empty, see [`parse_all_response_lines`]
+pub fn parse_response_line(input: &mut &str) -> ModalResult<Vec<Response>> {
     match parse_begin_line.parse_next(input)? {
         ResponseKind::Standard => {
             let file = parse_complete_angle_tag
                 .context(StrContext::Label("parse sm2 tag"))
                 .parse_next(input)?;
             ignore_space.parse_next(input)?;
-            let response = parse_response(input)?;
+            let (response, meta) = parse_rich_response(input)?;
 
-            // process filename
-            let mut file = file.replace('_', " ").chars().collect::<Vec<char>>();
-            file[0] = file[0].to_uppercase().next().unwrap();
-            let file = file.into_iter().collect::<String>();
-            Ok(vec![(file, response)])
+            Ok(vec![Response::new(file, response, meta)])
         }
-        ResponseKind::Vgs => {
+        ResponseKind::Vgs(cell) => {
+            let vgs = vgs_code(cell);
             let mut has_alternate = false;
             if input.contains("<br>") {
                 has_alternate = true;
@@ -180,8 +597,8 @@ pub fn parse_response_line(input: &mut &str) -> ModalResult<Vec<(String, String)
                 let first_response = take_until(0.., '<')
                     .context(StrContext::Label("response"))
                     .context(StrContext::Label("Parse VGS response text"))
-                    .parse_next(input)?
-                    .to_string();
+                    .map(rich_text)
+                    .parse_next(input)?;
 
                 parse_angle_tag
                     .context(StrContext::Label("expected <br>"))
@@ -191,8 +608,8 @@ pub fn parse_response_line(input: &mut &str) -> ModalResult<Vec<(String, String)
                 let second_response = take_until(0.., '|')
                     .context(StrContext::Label("response"))
                     .context(StrContext::Label("Parse VGS response text"))
-                    .parse_next(input)?
-                    .to_string();
+                    .map(rich_text)
+                    .parse_next(input)?;
 
                 parse_vertical_bar(input)?;
                 ignore_space(input)?;
@@ -210,15 +627,17 @@ pub fn parse_response_line(input: &mut &str) -> ModalResult<Vec<(String, String)
                     .parse_next(input)?;
 
                 Ok(vec![
-                    (first_file.to_string(), first_response),
-                    (second_file.to_string(), second_response),
+                    Response::new(first_file, first_response, ResponseMeta::default())
+                        .with_vgs(vgs.clone()),
+                    Response::new(second_file, second_response, ResponseMeta::default())
+                        .with_vgs(vgs),
                 ])
             } else {
                 let response = take_until(0.., '|')
                     .context(StrContext::Label("response"))
                     .context(StrContext::Label("Parse VGS response text"))
-                    .parse_next(input)?
-                    .to_string();
+                    .map(rich_text)
+                    .parse_next(input)?;
                 parse_vertical_bar(input)?;
                 ignore_space(input)?;
 
@@ -226,38 +645,94 @@ pub fn parse_response_line(input: &mut &str) -> ModalResult<Vec<(String, String)
                     .context(StrContext::Label("parse sm2 tag"))
                     .parse_next(input)?;
 
-                Ok(vec![(file.to_string(), response)])
+                Ok(vec![
+                    Response::new(file, response, ResponseMeta::default()).with_vgs(vgs)
+                ])
             }
         }
     }
 }
 
-pub fn parse_all_response_lines(input: &mut &str) -> ModalResult<Vec<Response>> {
+fn starts_with_vgs_cell(line: &str) -> bool {
+    line.strip_prefix('|')
+        .and_then(|rest| rest.split('|').next())
+        .and_then(vgs_code)
+        .is_some()
+}
+
+/// Parses every response on a page. Lines that fail to parse are skipped and
+/// recorded in [`ParsedPage::diagnostics`] rather than failing the whole page
+pub fn parse_all_response_lines(input: &mut &str) -> ParsedPage {
     // turn input into a vec of lines
-    // let input = input.lines().collect::<Vec<&str>>();
-    let re = Regex::new(r"\n|\\n|\r|\\r").unwrap();
-    let mut input = re.split(input);
-    let mut responses = Vec::new();
-    while let Some(mut line) = input.next() {
-        // check if line starts with <sm2>
-        if line.starts_with("* <sm2>") {
+    let re = Regex::new(r"\r\n|\\r\\n|\n|\\n|\r|\\r").unwrap();
+    let mut input = re.split(input).enumerate();
+    let mut page = ParsedPage::default();
+    let mut sections = Sections::default();
+    // VGS tables may give the key sequence its own cell before the line
+    let mut row_vgs = None;
+    while let Some((index, line)) = input.next() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("|-") {
+            row_vgs = None;
+            continue;
+        } else if let Some(code) = trimmed.strip_prefix(['|', '!']).and_then(vgs_code) {
+            row_vgs = Some(code);
+            continue;
+        }
+        let parsed = if let Ok((level, title)) = parse_heading.parse(trimmed) {
+            sections.enter(level, markup_text(title));
+            continue;
+        } else if let Ok(title) = parse_italic_heading.parse(trimmed) {
+            sections.enter(SUBHEADING_LEVEL, markup_text(title));
+            continue;
+        } else if line.starts_with("* <sm2>") {
+            // check if line starts with <sm2>
             // if it does, parse the line
-            let parsed = parse_response_line.parse_next(&mut line)?;
-            for (file, response) in parsed {
-                responses.push(Response { file, response });
-            }
+            parse_response_line
+                .parse_next(&mut &*line)
+                .map_err(|e| e.to_string())
         } else if line.starts_with("| align=\"left\" | \"") {
             // these lines contain information over two lines
-            let cur_line = line;
-            let next_line = input.next().unwrap();
-            let mut merged = cur_line.to_string();
-            merged.push_str(next_line);
-            let parsed = parse_response_line.parse_next(&mut merged.as_str())?;
-            for (file, response) in parsed {
-                responses.push(Response { file, response });
+            match input.next() {
+                Some((_, next_line)) => {
+                    let merged = format!("{line}{next_line}");
+                    parse_response_line
+                        .parse_next(&mut merged.as_str())
+                        .map_err(|e| e.to_string())
+                }
+                None => Err("table row is missing its second line".to_string()),
+            }
+        } else if line.contains("<sm2>") && starts_with_vgs_cell(line) {
+            // VGS rows that keep the whole row on one line
+            parse_response_line
+                .parse_next(&mut &*line)
+                .map_err(|e| e.to_string())
+        } else {
+            continue;
+        };
+
+        // a line without a file has no clip to look up
+        let parsed = parsed.and_then(|parsed| match parsed.iter().any(|r| r.file.is_empty()) {
+            true => Err("empty <sm2> tag".to_string()),
+            false => Ok(parsed),
+        });
+        match parsed {
+            Ok(parsed) => {
+                for mut response in parsed {
+                    response.category = sections.current();
+                    if response.vgs.is_none() && line.starts_with('|') {
+                        response.vgs.clone_from(&row_vgs);
+                    }
+                    page.responses.push(response);
+                }
             }
+            Err(error) => page.diagnostics.push(Diagnostic {
+                line: index + 1,
+                text: line.to_string(),
+                error,
+            }),
         }
     }
 
-    Ok(responses)
+    page
 }
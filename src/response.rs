@@ -1,13 +1,20 @@
+use crate::audio::{AudioFormat, AudioInfo, DownloadError};
+use crate::parsing::{escape_markdown, Rarity, Response as ParsedResponse, ResponseMeta, RichText};
 use crate::serde_response::*;
 use anyhow::Context as _;
 use anyhow::Result;
 use futures::future::join_all;
 use futures::FutureExt as _;
-use std::collections::HashMap;
+use futures::StreamExt as _;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher as _;
+use std::collections::{HashMap, HashSet};
 use std::sync::Mutex;
 use std::sync::OnceLock;
+use std::time::Duration;
 
 use rand::seq::IteratorRandom as _;
+use rand::seq::SliceRandom as _;
 
 const DOTA_URL_BASE: &str = "http://dota2.gamepedia.com";
 const DOTA_API_PATH: &str = "http://dota2.gamepedia.com/api.php";
@@ -20,8 +27,149 @@ pub struct Response {
     pub id: i32,
     pub processed_text: String,
     pub original_text: String,
+    /// The line with its formatting, empty for data saved before it was kept
+    #[serde(default)]
+    pub rich_text: RichText,
+    /// The wiki's name for the clip, ie `Vo abaddon abad spawn 01.mp3`
+    #[serde(default)]
+    pub file: String,
     pub response_link: String,
     pub hero_id: i32,
+    /// The section headings the line was found under, e.g. `["Drafting", "Picked"]`
+    #[serde(default)]
+    pub category: Vec<String>,
+    #[serde(default)]
+    pub meta: ResponseMeta,
+    /// The Smite VGS key sequence for this line, in upper case
+    #[serde(default)]
+    pub vgs: Option<String>,
+    /// Language code of the line, ie `en` or `ru`
+    #[serde(default = "default_language")]
+    pub language: String,
+    #[serde(default)]
+    pub audio: AudioInfo,
+}
+
+impl Response {
+    /// The extension to give the clip's file, from what was recorded when it
+    /// was ingested, the bytes of the file itself or its link, in that order
+    pub fn file_extension(&self, bytes: &[u8]) -> &str {
+        self.audio
+            .format()
+            .or_else(|| AudioFormat::sniff(bytes))
+            .map(AudioFormat::extension)
+            .or_else(|| {
+                // fandom links carry the file name before `/revision/latest`
+                let path = self.response_link.split(['?', '#']).next()?;
+                path.rsplit('/')
+                    .find_map(|segment| segment.rsplit_once('.'))
+                    .map(|(_, extension)| extension)
+            })
+            .unwrap_or("mp3")
+    }
+
+    /// The line formatted with Discord markdown
+    pub fn markdown(&self) -> String {
+        if self.rich_text.is_empty() {
+            escape_markdown(&self.original_text)
+        } else {
+            self.rich_text.markdown()
+        }
+    }
+}
+
+/// The language of the untranslated wiki pages
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+fn default_language() -> String {
+    DEFAULT_LANGUAGE.to_string()
+}
+
+/// Extra languages to ingest, from the comma separated `RESPONSE_LANGUAGES`
+/// environment variable, ie `ru,de`
+fn response_languages() -> Vec<String> {
+    std::env::var("RESPONSE_LANGUAGES")
+        .unwrap_or_default()
+        .split(',')
+        .map(|lang| lang.trim().to_lowercase())
+        .filter(|lang| !lang.is_empty() && lang != DEFAULT_LANGUAGE)
+        .collect()
+}
+
+/// Restricts which responses a lookup is allowed to return
+#[derive(Debug, Default, Clone)]
+pub struct ResponseFilter {
+    /// Only lines from these heroes, an empty list allows every hero
+    pub heroes: Vec<i32>,
+    /// Matches any heading in the response's category, ignoring case
+    pub category: Option<String>,
+    /// Only lines at least this rare
+    pub min_rarity: Option<Rarity>,
+    /// Matches the hero, ability or item a line is about, ignoring case
+    pub about: Option<String>,
+    /// Only lines from these kinds of page, an empty list allows every kind
+    pub kinds: Vec<PageKind>,
+    /// Never lines from these kinds of page
+    pub excluded_kinds: Vec<PageKind>,
+    /// Only lines from these games, an empty list allows every game
+    pub games: Vec<Game>,
+    /// Only lines in this language
+    pub language: Option<String>,
+    /// Only clips at most this long, clips of unknown length are allowed
+    pub max_duration: Option<Duration>,
+}
+
+impl ResponseFilter {
+    /// `hero` is the page the response came from, when it is known
+    pub fn matches(&self, response: &Response, hero: Option<&Hero>) -> bool {
+        if !self.heroes.is_empty() && !self.heroes.contains(&response.hero_id) {
+            return false;
+        }
+        if !self.kinds.is_empty() && !hero.is_some_and(|h| self.kinds.contains(&h.kind)) {
+            return false;
+        }
+        if hero.is_some_and(|h| self.excluded_kinds.contains(&h.kind)) {
+            return false;
+        }
+        if !self.games.is_empty() && !hero.is_some_and(|h| self.games.contains(&h.game)) {
+            return false;
+        }
+        if self
+            .language
+            .as_ref()
+            .is_some_and(|language| *language != response.language)
+        {
+            return false;
+        }
+        if let Some(category) = &self.category {
+            if !response
+                .category
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case(category))
+            {
+                return false;
+            }
+        }
+        if let (Some(max), Some(duration)) = (self.max_duration, response.audio.duration) {
+            if duration > max {
+                return false;
+            }
+        }
+        if self.min_rarity.is_some() && response.meta.rarity < self.min_rarity {
+            return false;
+        }
+        if let Some(about) = &self.about {
+            let meta = &response.meta;
+            if ![&meta.related_hero, &meta.related_ability]
+                .into_iter()
+                .flatten()
+                .any(|name| name.eq_ignore_ascii_case(about))
+            {
+                return false;
+            }
+        }
+        true
+    }
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -29,26 +177,109 @@ pub struct Hero {
     id: i32,
     hero_name: String,
     img_path: String,
+    #[serde(default)]
+    kind: PageKind,
+    /// The hero this page belongs to, for anything other than a base hero page
+    #[serde(default)]
+    parent: Option<String>,
+    /// The arcana, persona or pack name that sets this page apart from its parent
+    #[serde(default)]
+    variant: Option<String>,
+    /// The wiki the page came from
+    #[serde(default)]
+    game: Game,
+}
+
+/// What sort of page a set of responses was scraped from
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Deserialize,
+    serde::Serialize,
+    poise::ChoiceParameter,
+)]
+pub enum PageKind {
+    #[default]
+    Hero,
+    Arcana,
+    Persona,
+    Announcer,
+    #[name = "Mega-kill announcer"]
+    MegaKill,
+    Event,
+    /// A Smite god skin with its own voice lines
+    Skin,
+    /// A Smite voice pack or announcer pack
+    #[name = "Voice pack"]
+    VoicePack,
+}
+
+/// A response page's title broken down into who it belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageInfo {
+    pub name: String,
+    pub kind: PageKind,
+    pub parent: Option<String>,
+    pub variant: Option<String>,
+    /// The language the page's lines are in
+    pub language: String,
 }
 
+/// Event names that show up in the titles of event response pages
+const EVENTS: [&str; 8] = [
+    "Diretide",
+    "Frostivus",
+    "Siltbreaker",
+    "Aghanim's Labyrinth",
+    "Underhollow",
+    "Nemestice",
+    "Crownfall",
+    "Greeviling",
+];
+
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct ResponseDatabase {
     pub responses: Vec<Response>,
     pub heroes: HashMap<i32, Hero>,
     pub icons: HashMap<String, String>,
+    /// Response links that failed to download, to be fixed on the next update
+    #[serde(default)]
+    pub dead_links: HashSet<String>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Deserialize,
+    serde::Serialize,
+    poise::ChoiceParameter,
+)]
 pub enum Game {
+    #[default]
+    #[name = "Dota 2"]
     Dota,
     Smite,
 }
 
+impl Game {
+    pub const ALL: [Game; 2] = [Game::Dota, Game::Smite];
+}
+
 static HERO_ID: OnceLock<Mutex<i32>> = OnceLock::new();
 static RESPONSE_ID: OnceLock<Mutex<i32>> = OnceLock::new();
 
 impl ResponseDatabase {
-    async fn get_pages_for(&mut self, game: Game) -> Result<Vec<String>> {
+    /// Lists every response page for the game, classified by what it holds
+    async fn get_pages_for(&mut self, game: Game) -> Result<Vec<(String, PageInfo)>> {
         match game {
             Game::Dota => {
                 let params = {
@@ -75,9 +306,27 @@ impl ResponseDatabase {
                 let mut pages = vec![];
 
                 for category_members in pages_response.query.categorymembers {
-                    pages.push(category_members.title);
+                    let info = classify_page(&category_members.title);
+                    pages.push((category_members.title, info));
                 }
 
+                // translations live on subpages of the base hero pages, ie Abaddon/Responses/ru
+                let languages = response_languages();
+                let translations = pages
+                    .iter()
+                    .filter(|(_, info)| info.kind == PageKind::Hero)
+                    .flat_map(|(page, info)| {
+                        languages.iter().map(move |language| {
+                            let info = PageInfo {
+                                language: language.clone(),
+                                ..info.clone()
+                            };
+                            (format!("{}/{}", page, language), info)
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                pages.extend(translations);
+
                 Ok(pages)
             }
             Game::Smite => {
@@ -108,6 +357,7 @@ impl ResponseDatabase {
                 };
                 let mut pages = vec![];
                 let mut futures = vec![];
+                let mut categories = vec![];
 
                 for category_members in pages_response.query.categorymembers {
                     let mut params = params.clone();
@@ -122,15 +372,17 @@ impl ResponseDatabase {
                     let url = reqwest::Url::parse_with_params(SMITE_API_PATH, params)?;
                     let client = crate::Client::default();
                     futures.push(client.get(url).send());
+                    categories.push(category_members.title);
                 }
 
                 let results = join_all(futures).await;
-                for res in results.into_iter() {
+                for (res, category) in results.into_iter().zip(categories) {
                     match res {
                         Ok(json_response) => {
                             let pages_response = json_response.json::<PagesResponse>().await?;
                             for members_inner in pages_response.query.categorymembers {
-                                pages.push(members_inner.title);
+                                let info = classify_smite_page(&members_inner.title, &category);
+                                pages.push((members_inner.title, info));
                             }
                         },
                         Err(e) => return Err(e.into()),
@@ -145,37 +397,57 @@ impl ResponseDatabase {
 
     pub fn add_hero_and_responses(
         &mut self,
-        hero_name: String,
-        responses: Vec<(String, String, String)>,
+        info: PageInfo,
+        game: Game,
+        responses: Vec<(ParsedResponse, String, FileInfo)>,
     ) {
-        let mut hero_id = HERO_ID.get().unwrap().lock().unwrap();
-        self.heroes.insert(
-            *hero_id,
-            Hero {
-                id: *hero_id,
-                hero_name: hero_name.clone(),
-                img_path: format!("/media/dota2/images/{}.png", hero_name),
-            },
-        );
+        let mut next_hero_id = HERO_ID.get().unwrap().lock().unwrap();
+        // translated pages share the hero of the page they translate
+        let hero_id = match self.get_hero_id(&info.name) {
+            Some(id) if info.language != DEFAULT_LANGUAGE => id,
+            _ => {
+                let id = *next_hero_id;
+                *next_hero_id += 1;
+                self.heroes.insert(
+                    id,
+                    Hero {
+                        id,
+                        img_path: format!("/media/dota2/images/{}.png", info.name),
+                        hero_name: info.name,
+                        kind: info.kind,
+                        parent: info.parent,
+                        variant: info.variant,
+                        game,
+                    },
+                );
+                id
+            }
+        };
 
-        for (original_text, processed_text, response_link) in responses {
+        for (parsed, processed_text, file) in responses {
             let mut response_id = RESPONSE_ID.get().unwrap().lock().unwrap();
             self.responses.push(Response {
                 id: *response_id,
                 processed_text,
-                original_text,
-                response_link,
-                hero_id: *hero_id,
+                original_text: parsed.response,
+                rich_text: parsed.rich_text,
+                file: parsed.file,
+                response_link: file.url,
+                hero_id,
+                category: parsed.category,
+                meta: parsed.meta,
+                vgs: parsed.vgs,
+                language: info.language.clone(),
+                audio: file.audio,
             });
             *response_id += 1;
         }
-        *hero_id += 1;
     }
 
     pub fn get_hero_id(&self, name: &str) -> Option<i32> {
         self.heroes
             .iter()
-            .find(|(_, h)| h.hero_name == name)
+            .find(|(_, h)| h.hero_name.eq_ignore_ascii_case(name))
             .map(|(id, _)| *id)
     }
 
@@ -183,6 +455,30 @@ impl ResponseDatabase {
         self.heroes.get(&id).map(|h| h.hero_name.as_str())
     }
 
+    /// The name of the hero a page belongs to, which is the page's own name
+    /// for base hero pages
+    pub fn get_base_hero_name(&self, id: i32) -> Option<&str> {
+        self.heroes
+            .get(&id)
+            .map(|h| h.parent.as_deref().unwrap_or(&h.hero_name))
+    }
+
+    pub fn get_variant(&self, id: i32) -> Option<&str> {
+        self.heroes.get(&id).and_then(|h| h.variant.as_deref())
+    }
+
+    /// Ids of the named hero along with its arcana, persona and other variants
+    pub fn get_hero_family(&self, name: &str) -> Vec<i32> {
+        self.heroes
+            .values()
+            .filter(|h| {
+                h.hero_name.eq_ignore_ascii_case(name)
+                    || h.parent.as_deref().is_some_and(|p| p.eq_ignore_ascii_case(name))
+            })
+            .map(|h| h.id)
+            .collect()
+    }
+
     pub fn get_img_dir(&self, id: i32) -> Option<&str> {
         self.heroes.get(&id).map(|h| h.img_path.as_str())
     }
@@ -191,25 +487,186 @@ impl ResponseDatabase {
         self.heroes.values().map(|h| h.hero_name.as_str()).collect()
     }
 
-    pub fn get_response(&self, processed_text: &str, hero_id: Option<i32>) -> Option<&Response> {
-        match hero_id {
-            Some(id) => self
-                .responses
-                .iter()
-                .filter(|r| r.processed_text == processed_text && r.hero_id == id)
-                .choose(&mut rand::thread_rng()),
-            None => self
-                .responses
-                .iter()
-                .filter(|r| r.processed_text == processed_text)
-                .choose(&mut rand::thread_rng()),
+    /// Recomputes every response's processed text from its original text, so
+    /// data saved with older text processing still matches
+    pub fn reprocess_text(&mut self) {
+        for response in self.responses.iter_mut() {
+            response.processed_text = crate::process_text(&response.original_text);
         }
     }
 
+    /// Every response the filter allows
+    fn filtered<'a, 'f>(
+        &'a self,
+        filter: &'f ResponseFilter,
+    ) -> impl Iterator<Item = &'a Response> + use<'a, 'f> {
+        self.responses
+            .iter()
+            .filter(|r| filter.matches(r, self.heroes.get(&r.hero_id)))
+    }
+
+    pub fn get_response(&self, processed_text: &str, filter: &ResponseFilter) -> Option<&Response> {
+        self.filtered(filter)
+            .filter(|r| r.processed_text == processed_text)
+            .choose(&mut rand::thread_rng())
+    }
+
+    /// Finds the longest line of at least `min_words` words that appears
+    /// anywhere in `processed_text`, word for word
+    pub fn find_in_text(
+        &self,
+        processed_text: &str,
+        min_words: usize,
+        filter: &ResponseFilter,
+    ) -> Option<&Response> {
+        let padded = format!(" {} ", processed_text);
+        let found = self
+            .filtered(filter)
+            .filter(|r| r.processed_text.split(' ').count() >= min_words)
+            .filter(|r| padded.contains(&format!(" {} ", r.processed_text)))
+            .collect::<Vec<_>>();
+        let longest = found.iter().map(|r| r.processed_text.len()).max()?;
+        found
+            .into_iter()
+            .filter(|r| r.processed_text.len() == longest)
+            .choose(&mut rand::thread_rng())
+    }
+
+    pub fn random_response(&self, filter: &ResponseFilter) -> Option<&Response> {
+        self.filtered(filter).choose(&mut rand::thread_rng())
+    }
+
+    /// Fuzzy finds the response that best matches the given phrase
+    pub fn search(&self, phrase: &str, filter: &ResponseFilter) -> Option<&Response> {
+        let matcher = SkimMatcherV2::default();
+        let phrase = crate::process_text(phrase);
+        self.filtered(filter)
+            .filter_map(|r| {
+                matcher
+                    .fuzzy_match(&r.processed_text, &phrase)
+                    .map(|score| (score, r))
+            })
+            .max_by_key(|(score, _)| *score)
+            .map(|(_, r)| r)
+    }
+
+    /// Splits `text` into whole lines, taking the longest line that starts
+    /// each remaining stretch of words, so the sentence takes as few clips as
+    /// greedily possible. Gives the first word no line covers when it fails
+    pub fn cover_sentence(
+        &self,
+        text: &str,
+        filter: &ResponseFilter,
+    ) -> Result<Vec<&Response>, String> {
+        let processed = crate::process_text(text);
+        let words = processed.split(' ').filter(|w| !w.is_empty()).collect::<Vec<_>>();
+        let mut lines: HashMap<&str, Vec<&Response>> = HashMap::new();
+        for response in self.filtered(filter) {
+            lines
+                .entry(response.processed_text.as_str())
+                .or_default()
+                .push(response);
+        }
+
+        let mut pieces = vec![];
+        let mut start = 0;
+        while start < words.len() {
+            let (end, response) = (start + 1..=words.len())
+                .rev()
+                .find_map(|end| {
+                    let candidates = lines.get(words[start..end].join(" ").as_str())?;
+                    Some((end, *candidates.choose(&mut rand::thread_rng())?))
+                })
+                .ok_or_else(|| words[start].to_string())?;
+            pieces.push(response);
+            start = end;
+        }
+        Ok(pieces)
+    }
+
+    /// Picks a line for a Smite VGS key sequence, ie `VVGG`
+    pub fn get_vgs_response(&self, code: &str, filter: &ResponseFilter) -> Option<&Response> {
+        self.filtered(filter)
+            .filter(|r| r.vgs.as_deref() == Some(code))
+            .choose(&mut rand::thread_rng())
+    }
+
+    /// Finds a line from the hero targeted by `res` directed back at its speaker
+    pub fn get_reply(&self, res: &Response, filter: &ResponseFilter) -> Option<&Response> {
+        let targets = self.get_hero_family(res.meta.target_hero.as_deref()?);
+        let speaker = self.get_base_hero_name(res.hero_id)?;
+        self.filtered(filter)
+            .filter(|r| targets.contains(&r.hero_id))
+            .filter(|r| {
+                r.meta
+                    .target_hero
+                    .as_deref()
+                    .is_some_and(|target| target.eq_ignore_ascii_case(speaker))
+            })
+            .choose(&mut rand::thread_rng())
+    }
+
+    /// The line behind a clip, by the wiki's name for it
+    pub fn get_by_file(&self, file: &str) -> Option<&Response> {
+        self.responses.iter().find(|r| !r.file.is_empty() && r.file == file)
+    }
+
     pub fn get_icon_url(&self, name: &str) -> Option<&str> {
         self.icons.get(name).map(|s| s.as_str())
     }
 
+    /// Every distinct link in the database, along with what is needed to look
+    /// it up again
+    pub fn links_to_check(&self) -> Vec<LinkCheck> {
+        let mut seen = HashSet::new();
+        self.responses
+            .iter()
+            .filter(|r| seen.insert(r.response_link.as_str()))
+            .map(|r| LinkCheck {
+                link: r.response_link.clone(),
+                file: r.file.clone(),
+                game: self.heroes.get(&r.hero_id).map(|h| h.game).unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Points responses at the new links found by [`verify_links`] and records
+    /// the links that could not be fixed
+    pub fn apply_link_report(&mut self, report: &LinkReport) {
+        for response in self.responses.iter_mut() {
+            if let Some(file) = report.repaired.get(&response.response_link) {
+                response.response_link.clone_from(&file.url);
+                response.audio = file.audio.clone();
+            }
+        }
+        for link in report.repaired.keys() {
+            self.dead_links.remove(link);
+        }
+        for check in &report.unresolved {
+            self.mark_dead_link(&check.link);
+        }
+    }
+
+    pub fn mark_dead_link(&mut self, link: &str) {
+        if self.dead_links.insert(link.to_string()) {
+            tracing::warn!("Marked {} as a dead link", link);
+        }
+    }
+
+    /// Drops dead links that no response uses any more, which happens once an
+    /// update has found the files' new links
+    pub fn forget_repaired_links(&mut self) {
+        let before = self.dead_links.len();
+        let responses = &self.responses;
+        self.dead_links
+            .retain(|link| responses.iter().any(|r| &r.response_link == link));
+        tracing::info!(
+            "Repaired {} dead links, {} remain",
+            before - self.dead_links.len(),
+            self.dead_links.len()
+        );
+    }
+
     pub fn is_hero_response(&self, processed_text: &str) -> bool {
         self.responses
             .iter()
@@ -240,14 +697,8 @@ impl ResponseDatabase {
             .unwrap();
         // TODO: handle errors rather than simply throwing them away
 
-        let get_fut_and_names = pages.iter().map(|page| {
-            let hero_name = if is_hero_type(page) {
-                get_hero_name(page)
-            } else {
-                page.clone()
-            };
-
-            tracing::info!("Fetching responses for {}", hero_name);
+        let get_fut_and_names = pages.into_iter().map(|(page, info)| {
+            tracing::info!("Fetching responses for {}", info.name);
 
             let params = HashMap::from([("action", "raw")]);
             let url = match game {
@@ -261,38 +712,38 @@ impl ResponseDatabase {
                     url
                 }
             };
-            (client.get(url).send().fuse(), hero_name)
+            (client.get(url).send().fuse(), info)
         });
 
-        let (get_fut, hero_names): (Vec<_>, Vec<_>) = get_fut_and_names.into_iter().unzip();
+        let (get_fut, page_infos): (Vec<_>, Vec<_>) = get_fut_and_names.into_iter().unzip();
 
         // await all the futures, filter out the errors
         // note: we also have to filter out the errors in the hero names
         let text_fut_and_names = futures::future::join_all(get_fut)
             .await
             .into_iter()
-            .zip(hero_names.into_iter())
-            .filter_map(|(r, name)| match r {
-                Ok(v) => Some((v, name)),
+            .zip(page_infos)
+            .filter_map(|(r, info)| match r {
+                Ok(v) => Some((v, info)),
                 Err(e) => {
                     tracing::error!("An Error was detected {}", e);
                     None
             }
             })
-            .map(|(r, name)| (r.text().fuse(), name))
+            .map(|(r, info)| (r.text().fuse(), info))
             .collect::<Vec<_>>();
 
         // we need the vec of futures separately for the next step
-        let (text_fut, hero_names): (Vec<_>, Vec<_>) = text_fut_and_names.into_iter().unzip();
+        let (text_fut, page_infos): (Vec<_>, Vec<_>) = text_fut_and_names.into_iter().unzip();
 
         // await all the futures, filter out the errors
         // note: we also have to filter out the errors in the hero names
         let text_and_names = futures::future::join_all(text_fut)
             .await
             .into_iter()
-            .zip(hero_names)
-            .filter_map(|(r, name)| match r {
-                Ok(v) => Some((v, name)),
+            .zip(page_infos)
+            .filter_map(|(r, info)| match r {
+                Ok(v) => Some((v, info)),
                 Err(e) => {
                     tracing::error!("An error was detected {}", e);
                     None
@@ -301,25 +752,25 @@ impl ResponseDatabase {
             .collect::<Vec<_>>();
 
         let mut futures = vec![];
-        let mut hero_names = vec![];
-        for (responses_source, hero_name) in text_and_names {
-            if hero_name.starts_with("Zhong Kui") {
+        let mut page_infos = vec![];
+        for (responses_source, info) in text_and_names {
+            if info.name.starts_with("Zhong Kui") {
                 dbg!(&responses_source);
             }
-            tracing::info!("Creating response list for {}", hero_name);
+            tracing::info!("Creating response list for {}", info.name);
             let response_link_list_fut =
-                create_responses_text_and_link_list(hero_name.clone(), responses_source, game);
+                create_responses_text_and_link_list(info.name.clone(), responses_source, game);
             futures.push(response_link_list_fut);
-            hero_names.push(hero_name);
+            page_infos.push(info);
         }
 
         let responses = join_all(futures).await;
-        for (response, hero_name) in responses.into_iter().zip(hero_names) {
-            tracing::info!("Adding responses for {}", hero_name);
-            if hero_name.starts_with("Zhong Kui") {
+        for (response, info) in responses.into_iter().zip(page_infos) {
+            tracing::info!("Adding responses for {}", info.name);
+            if info.name.starts_with("Zhong Kui") {
                 dbg!(&response);
             }
-            self.add_hero_and_responses(hero_name, response);
+            self.add_hero_and_responses(info, game, response);
         }
 
         tracing::info!("Hero responses complete");
@@ -341,27 +792,145 @@ impl ResponseDatabase {
     }
 }
 
-fn is_hero_type(page: &str) -> bool {
-    page.ends_with("/Responses")
+/// Works out what kind of page a title refers to and which hero it belongs to
+///
+/// * `Abaddon/Responses` is a base hero page
+/// * `Juggernaut/Responses/Bladeform Legacy` or `Juggernaut/Bladeform Legacy/Responses`
+///   is an arcana, or a persona if the variant says so
+/// * `Axe Announcer Pack` and `Axe Mega-Kill Announcer Pack` are announcer packs
+///   belonging to Axe
+/// * anything mentioning an event is an event page
+pub fn classify_page(page: &str) -> PageInfo {
+    let segments = page.split('/').map(str::trim).collect::<Vec<&str>>();
+    let title = segments.iter().filter(|s| **s != "Responses").cloned().collect::<Vec<_>>();
+
+    let kind = if page.contains("Mega-Kill") {
+        PageKind::MegaKill
+    } else if page.contains("Announcer") {
+        PageKind::Announcer
+    } else if EVENTS.iter().any(|event| page.contains(event)) {
+        PageKind::Event
+    } else if page.contains("Persona") {
+        PageKind::Persona
+    } else if title.len() > 1 {
+        PageKind::Arcana
+    } else {
+        PageKind::Hero
+    };
+
+    match kind {
+        PageKind::Hero => PageInfo {
+            name: title.first().unwrap_or(&page).to_string(),
+            kind,
+            parent: None,
+            variant: None,
+            language: default_language(),
+        },
+        PageKind::Announcer | PageKind::MegaKill if title.len() == 1 => {
+            let parent = ["Mega-Kill Announcer Pack", "Announcer Pack", "Mega-Kill Pack", "Announcer"]
+                .iter()
+                .find_map(|suffix| title[0].strip_suffix(suffix))
+                .map(str::trim)
+                .filter(|parent| !parent.is_empty())
+                .map(str::to_string);
+            PageInfo {
+                name: title[0].to_string(),
+                kind,
+                parent,
+                variant: Some(title[0].to_string()),
+                language: default_language(),
+            }
+        }
+        _ if title.len() > 1 => {
+            let variant = title[1..].join(" ");
+            PageInfo {
+                name: format!("{} ({})", title[0], variant),
+                kind,
+                parent: Some(title[0].to_string()),
+                variant: Some(variant),
+                language: default_language(),
+            }
+        }
+        _ => PageInfo {
+            name: page.to_string(),
+            kind,
+            parent: None,
+            variant: None,
+            language: default_language(),
+        },
+    }
 }
 
-fn get_hero_name(page: &str) -> String {
-    page.split('/').next().unwrap().to_string()
+/// Works out whether a Smite voiceline page belongs to a god, one of its skins
+/// or a voice pack, using the category it was listed under
+///
+/// * `Agni voicelines` is the base voice pack for Agni
+/// * `Agni/Dragon Fire voicelines` or anything in a skin category is a skin
+/// * anything in an announcer or voice pack category is a voice pack
+pub fn classify_smite_page(page: &str, category: &str) -> PageInfo {
+    let title = page
+        .trim_end_matches("/Voicelines")
+        .trim_end_matches(" voicelines")
+        .trim_end_matches(" Voicelines")
+        .trim();
+    let category = category.to_lowercase();
+    let (god, skin) = match title.split_once('/') {
+        Some((god, skin)) => (god.trim(), Some(skin.trim())),
+        None => (title, None),
+    };
+
+    if category.contains("announcer") || category.contains("voice pack") {
+        PageInfo {
+            name: title.to_string(),
+            kind: PageKind::VoicePack,
+            parent: skin.map(|_| god.to_string()),
+            variant: Some(skin.unwrap_or(title).to_string()),
+            language: default_language(),
+        }
+    } else if let Some(skin) = skin {
+        PageInfo {
+            name: format!("{} ({})", god, skin),
+            kind: PageKind::Skin,
+            parent: Some(god.to_string()),
+            variant: Some(skin.to_string()),
+            language: default_language(),
+        }
+    } else if category.contains("skin") {
+        PageInfo {
+            name: title.to_string(),
+            kind: PageKind::Skin,
+            parent: None,
+            variant: Some(title.to_string()),
+            language: default_language(),
+        }
+    } else {
+        PageInfo {
+            name: title.to_string(),
+            kind: PageKind::Hero,
+            parent: None,
+            variant: None,
+            language: default_language(),
+        }
+    }
 }
 
 async fn create_responses_text_and_link_list(
     hero_name: String,
     responses_source: String,
     game: Game,
-) -> Vec<(String, String, String)> {
-    let mut responses: Vec<(String, String, String)> = vec![];
-    let file_and_text_list = match crate::parsing::parse_all_response_lines(&mut responses_source.as_str()) {
-        Ok(v) => v,
-        Err(e) => {
-            tracing::error!("An Error Occurred While Parsing responses for {}:\n{}", hero_name, e);
-            return responses;
-        }
-    };
+) -> Vec<(ParsedResponse, String, FileInfo)> {
+    let mut responses: Vec<(ParsedResponse, String, FileInfo)> = vec![];
+    let page = crate::parsing::parse_all_response_lines(&mut responses_source.as_str());
+    for diagnostic in &page.diagnostics {
+        tracing::warn!(
+            "Skipped line {} while parsing responses for {}: {}\n{}",
+            diagnostic.line,
+            hero_name,
+            diagnostic.text,
+            diagnostic.error
+        );
+    }
+    let file_and_text_list = page.responses;
 
     let files_list = file_and_text_list
         .iter()
@@ -374,7 +943,7 @@ async fn create_responses_text_and_link_list(
         if !processed_text.is_empty() {
             let link = file_and_link_map.get(&response.file);
             if let Some(v) = link {
-                responses.push((response.response, processed_text, v.clone()));
+                responses.push((response, processed_text, v.clone()));
             } else {
                 tracing::warn!("No link found for {}", response.file);
             }
@@ -384,10 +953,146 @@ async fn create_responses_text_and_link_list(
     responses
 }
 
-async fn links_for_files(files: &[&String], game: Game) -> HashMap<String, String> {
+/// Where a response's audio lives and what is known about it
+#[derive(Debug, Clone)]
+pub struct FileInfo {
+    pub url: String,
+    pub audio: AudioInfo,
+}
+
+/// A stored link to check, along with what is needed to look it up again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkCheck {
+    pub link: String,
+    /// The wiki's name for the file, empty for data saved before it was kept
+    pub file: String,
+    pub game: Game,
+}
+
+/// What [`verify_links`] found
+#[derive(Debug, Default)]
+pub struct LinkReport {
+    pub checked: usize,
+    /// The new location of each broken link that could be found again, keyed
+    /// by the broken link
+    pub repaired: HashMap<String, FileInfo>,
+    /// Broken links the wiki no longer has a file for
+    pub unresolved: Vec<LinkCheck>,
+    /// Links that could not be checked, ie because the wiki timed out or was
+    /// rate limiting
+    pub unreachable: usize,
+}
+
+/// HEAD checks every link, `concurrency` at a time, and looks up the files of
+/// any that are broken again through the wiki's `imageinfo` API
+pub async fn verify_links(
+    client: &crate::Client,
+    checks: Vec<LinkCheck>,
+    concurrency: usize,
+) -> LinkReport {
+    let mut report = LinkReport {
+        checked: checks.len(),
+        ..Default::default()
+    };
+    let statuses = futures::stream::iter(checks)
+        .map(|check| async move {
+            let status = client.head(&check.link).send().await.map(|r| r.status());
+            (check, status)
+        })
+        .buffer_unordered(concurrency)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut broken = vec![];
+    for (check, status) in statuses {
+        match status {
+            Ok(status) if status.is_success() => (),
+            // the same statuses a download gives up on, so a throttled or
+            // HEAD-shy server doesn't get working links reported as broken
+            Ok(status) if DownloadError::Status(status).is_dead_link() => broken.push(check),
+            Ok(status) => {
+                tracing::warn!("Could not check {}: server answered with {}", check.link, status);
+                report.unreachable += 1;
+            }
+            Err(e) => {
+                tracing::warn!("Could not check {}: {:?}", check.link, e);
+                report.unreachable += 1;
+            }
+        }
+    }
+    tracing::info!("Found {} broken links", broken.len());
+
+    let mut broken_by_game: HashMap<Game, Vec<LinkCheck>> = HashMap::new();
+    for check in broken {
+        broken_by_game.entry(check.game).or_default().push(check);
+    }
+    for (game, checks) in broken_by_game {
+        let files = checks
+            .iter()
+            .filter(|c| !c.file.is_empty())
+            .map(|c| &c.file)
+            .collect::<Vec<_>>();
+        let found = if files.is_empty() {
+            HashMap::new()
+        } else {
+            links_for_files(&files, game).await
+        };
+        for check in checks {
+            match found.get(&check.file) {
+                Some(file) if file.url != check.link => {
+                    report.repaired.insert(check.link.clone(), file.clone());
+                }
+                _ => report.unresolved.push(check),
+            }
+        }
+    }
+
+    report
+}
+
+/// The files a batch of `imageinfo` queries found, by the name they were asked
+/// for. Missing files are left out, moved ones are found under their old name
+pub fn files_in_batch(query: BatchQuery) -> HashMap<String, FileInfo> {
+    let mut files = HashMap::new();
+    for page in query.pages.into_values() {
+        if page.missing.is_some() {
+            continue;
+        }
+        let Some(image_info) = page.imageinfo.first() else {
+            continue;
+        };
+        let file = FileInfo {
+            url: image_info.url.clone(),
+            audio: AudioInfo {
+                mime: image_info.mime.clone(),
+                size: image_info.size,
+                duration: image_info
+                    .duration
+                    .and_then(|secs| Duration::try_from_secs_f64(secs).ok()),
+            },
+        };
+        files.insert(page.title, file);
+    }
+    // the wiki normalizes a title before following its redirect, so undo
+    // the redirects first
+    for rename in query.redirects.iter().chain(&query.normalized) {
+        if let Some(file) = files.get(&rename.to).cloned() {
+            files.insert(rename.from.clone(), file);
+        }
+    }
+    files
+        .into_iter()
+        .filter_map(|(title, file)| Some((title.strip_prefix("File:")?.to_string(), file)))
+        .collect()
+}
+
+/// How many files to probe for their length at once
+const PROBE_CONCURRENCY: usize = 8;
+
+async fn links_for_files(files: &[&String], game: Game) -> HashMap<String, FileInfo> {
     fn get_params_for_files_api(files: Option<&[String]>) -> HashMap<String, String> {
-        let titles = if files.is_some() {
-            format!("File:{}", files.unwrap().join("|File:"))
+        let titles = if let Some(files) = files {
+            format!("File:{}", files.join("|File:"))
         } else {
             String::new()
         };
@@ -396,7 +1101,9 @@ async fn links_for_files(files: &[&String], game: Game) -> HashMap<String, Strin
             ("action".to_string(), "query".to_string()),
             ("titles".to_string(), titles),
             ("prop".to_string(), "imageinfo".to_string()),
-            ("iiprop".to_string(), "url".to_string()),
+            // find moved files under their new name
+            ("redirects".to_string(), "1".to_string()),
+            ("iiprop".to_string(), "url|size|mime".to_string()),
             ("format".to_string(), "json".to_string()),
         ])
     }
@@ -405,7 +1112,7 @@ async fn links_for_files(files: &[&String], game: Game) -> HashMap<String, Strin
     let file_title_prefix_length = "%7CFile%3A".len();
     let max_header_length = 1960;
 
-    let mut files_link_mapping: HashMap<String, String> = HashMap::new();
+    let mut files_link_mapping: HashMap<String, FileInfo> = HashMap::new();
     let empty_api_length = match game {
         Game::Dota => {
             reqwest::Url::parse_with_params(DOTA_API_PATH, get_params_for_files_api(None))
@@ -474,15 +1181,7 @@ async fn links_for_files(files: &[&String], game: Game) -> HashMap<String, Strin
         match res {
             Ok(res) => match res.json::<BatchResponse>().await {
                 Ok(json_response) => {
-                    let pages = json_response.query.pages;
-
-                    for page in pages.values() {
-                        let title = page.title.clone();
-                        let image_info = &page.imageinfo;
-                        let url = image_info[0].url.clone();
-                        // let url = format!("{}{}", url.split_once(".ogg").unwrap().0, ".ogg");
-                        files_link_mapping.insert(title.chars().skip(5).collect::<String>(), url);
-                    }
+                    files_link_mapping.extend(files_in_batch(json_response.query));
                 }
                 Err(e) => {
                     tracing::warn!("{:?}", e);
@@ -494,5 +1193,29 @@ async fn links_for_files(files: &[&String], game: Game) -> HashMap<String, Strin
         }
     }
 
+    // the wiki doesn't always know how long a file is, so read it from the
+    // file's own headers
+    let unprobed = files_link_mapping
+        .iter()
+        .filter(|(_, file)| file.audio.duration.is_none())
+        .map(|(name, file)| (name.clone(), file.clone()))
+        .collect::<Vec<_>>();
+    let probed = futures::stream::iter(unprobed)
+        .map(|(name, file)| {
+            let client = client.clone();
+            async move {
+                let audio = crate::audio::probe_url(&client, &file.url, file.audio).await;
+                (name, audio)
+            }
+        })
+        .buffer_unordered(PROBE_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+    for (name, audio) in probed {
+        if let Some(file) = files_link_mapping.get_mut(&name) {
+            file.audio = audio;
+        }
+    }
+
     files_link_mapping
 }
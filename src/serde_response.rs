@@ -31,12 +31,21 @@ pub struct BatchResponse {
 
 #[derive(Debug, serde::Deserialize)]
 pub struct BatchQuery {
-    #[serde(skip)]
-    #[allow(dead_code)]
-    pub normalized: String,
+    /// Titles the wiki spelled differently from how they were asked for
+    #[serde(default)]
+    pub normalized: Vec<Rename>,
+    /// Files that were moved, from their old title to the new one
+    #[serde(default)]
+    pub redirects: Vec<Rename>,
     pub pages: HashMap<String, BatchPage>,
 }
 
+#[derive(Debug, serde::Deserialize)]
+pub struct Rename {
+    pub from: String,
+    pub to: String,
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct BatchPage {
     #[serde(skip)]
@@ -46,12 +55,25 @@ pub struct BatchPage {
     #[allow(dead_code)]
     pub ns: i32,
     pub title: String,
+    /// Set for a file that was deleted or never existed
+    #[serde(default)]
+    pub missing: Option<serde::de::IgnoredAny>,
+    /// Empty for a missing file
+    #[serde(default)]
     pub imageinfo: Vec<ImageInfo>,
 }
 
 #[derive(Debug, serde::Deserialize)]
 pub struct ImageInfo {
     pub url: String,
+    /// In bytes
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub mime: Option<String>,
+    /// In seconds, only given for audio and video files
+    #[serde(default)]
+    pub duration: Option<f64>,
     #[allow(dead_code)]
     #[serde(alias = "descriptionurl", skip)]
     pub description_url: String,
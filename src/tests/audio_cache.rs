@@ -0,0 +1,202 @@
+use std::path::PathBuf;
+
+use crate::cache::AudioCache;
+
+/// A fresh directory for a test's cache
+fn cache_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("shake_bot_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[tokio::test]
+async fn test_cache_roundtrip() {
+    let dir = cache_dir("roundtrip");
+    let cache = AudioCache::open(&dir, 1024).unwrap();
+    assert_eq!(cache.get("a").await, None);
+
+    cache.insert("a", b"clip").await.unwrap();
+    // a second link to the same clip shares its file
+    cache.insert("b", b"clip").await.unwrap();
+    assert_eq!(cache.get("a").await.as_deref(), Some(&b"clip"[..]));
+    assert_eq!(cache.get("b").await.as_deref(), Some(&b"clip"[..]));
+    assert_eq!(cache.size(), 4);
+
+    // the index survives a restart
+    let cache = AudioCache::open(&dir, 1024).unwrap();
+    assert!(cache.contains("a"));
+    assert_eq!(cache.get("b").await.as_deref(), Some(&b"clip"[..]));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_cache_evicts_least_recently_used() {
+    let dir = cache_dir("eviction");
+    let cache = AudioCache::open(&dir, 10).unwrap();
+    cache.insert("a", b"aaaa").await.unwrap();
+    cache.insert("b", b"bbbb").await.unwrap();
+    // using `a` makes `b` the oldest
+    cache.get("a").await.unwrap();
+    cache.insert("c", b"cccc").await.unwrap();
+
+    assert!(cache.contains("a"));
+    assert!(!cache.contains("b"));
+    assert!(cache.contains("c"));
+    assert_eq!(cache.size(), 8);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+
+    // too big to ever fit
+    cache.insert("d", &[0; 11]).await.unwrap();
+    assert!(!cache.contains("d"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Answers every request on a local port with `response`, giving its url
+async fn serve(response: Vec<u8>) -> String {
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut request = [0; 1024];
+            let _ = socket.read(&mut request).await;
+            let _ = socket.write_all(&response).await;
+        }
+    });
+    format!("http://{}/clip.mp3", addr)
+}
+
+#[tokio::test]
+async fn test_download_failures() {
+    use crate::audio::{download, DownloadError, MAX_DOWNLOAD_BYTES};
+
+    let client = crate::Client::default();
+    let url = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nclip".to_vec()).await;
+    assert_eq!(download(&client, &url).await.unwrap(), b"clip");
+
+    let url = serve(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec()).await;
+    let error = download(&client, &url).await.unwrap_err();
+    assert!(error.is_dead_link(), "{error}");
+
+    let too_large = format!(
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+        MAX_DOWNLOAD_BYTES + 1
+    );
+    let url = serve(too_large.into_bytes()).await;
+    let error = download(&client, &url).await.unwrap_err();
+    assert!(matches!(error, DownloadError::TooLarge), "{error}");
+    assert!(!error.is_dead_link());
+}
+
+#[tokio::test]
+async fn test_prefetch_stops_when_full() {
+    use crate::cache::PrefetchReport;
+
+    let dir = cache_dir("prefetch");
+    let cache = AudioCache::open(&dir, 10).unwrap();
+    cache.insert("a", b"aaaa").await.unwrap();
+    let mut urls = vec![];
+    for clip in ["bbbb", "cccc", "dddd"] {
+        let response = format!("HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\n{}", clip);
+        urls.push(serve(response.into_bytes()).await);
+    }
+
+    let client = crate::Client::default();
+    let report = cache.prefetch(&client, urls, 1).await;
+    assert_eq!(
+        report,
+        PrefetchReport {
+            fetched: 1,
+            failed: 0,
+            skipped: 2,
+        }
+    );
+    // nothing already cached made room
+    assert!(cache.contains("a"));
+    assert_eq!(cache.size(), 8);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+
+    // the index was saved once it was done
+    let cache = AudioCache::open(&dir, 10).unwrap();
+    assert_eq!(cache.size(), 8);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_verify_links() {
+    use crate::response::{verify_links, Game, LinkCheck};
+
+    let check = |link: String| LinkCheck {
+        link,
+        file: String::new(),
+        game: Game::Dota,
+    };
+    let alive = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec()).await;
+    let dead = serve(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec()).await;
+
+    let no_head =
+        serve(b"HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\n\r\n".to_vec()).await;
+    let throttled =
+        serve(b"HTTP/1.1 429 Too Many Requests\r\nContent-Length: 0\r\n\r\n".to_vec()).await;
+
+    // without retries, which would only wait out the rate limit
+    let client = crate::Client {
+        client: reqwest_middleware::ClientBuilder::new(reqwest::Client::new()).build(),
+    };
+    let checks = vec![
+        check(alive),
+        check(dead.clone()),
+        check(no_head),
+        check(throttled),
+    ];
+    let report = verify_links(&client, checks, 2).await;
+    assert_eq!(report.checked, 4);
+    assert!(report.repaired.is_empty());
+    // without a file name there is nothing to look up again
+    assert_eq!(report.unresolved, [check(dead)]);
+    // neither says the link is gone
+    assert_eq!(report.unreachable, 2);
+}
+
+#[test]
+fn test_files_in_batch() {
+    use crate::response::files_in_batch;
+    use crate::serde_response::BatchResponse;
+
+    // one file kept its name, one was moved and one was deleted
+    let json = r#"{
+        "batchcomplete": "",
+        "query": {
+            "normalized": [
+                {"from": "File:Vo_axe_kill_01.mp3", "to": "File:Vo axe kill 01.mp3"},
+                {"from": "File:Vo_axe_old.mp3", "to": "File:Vo axe old.mp3"}
+            ],
+            "redirects": [
+                {"from": "File:Vo axe old.mp3", "to": "File:Vo axe new.mp3"}
+            ],
+            "pages": {
+                "-1": {"ns": 6, "title": "File:Vo axe gone.mp3", "missing": "", "imagerepository": ""},
+                "10": {"pageid": 10, "ns": 6, "title": "File:Vo axe kill 01.mp3", "imageinfo": [
+                    {"url": "https://example.com/kill.mp3", "size": 100, "mime": "audio/mpeg"}
+                ]},
+                "11": {"pageid": 11, "ns": 6, "title": "File:Vo axe new.mp3", "imageinfo": [
+                    {"url": "https://example.com/new.mp3", "size": 200, "mime": "audio/mpeg", "duration": 1.5}
+                ]}
+            }
+        }
+    }"#;
+    let batch: BatchResponse = serde_json::from_str(json).unwrap();
+    let files = files_in_batch(batch.query);
+
+    assert_eq!(files["Vo_axe_kill_01.mp3"].url, "https://example.com/kill.mp3");
+    assert_eq!(files["Vo_axe_old.mp3"].url, "https://example.com/new.mp3");
+    assert_eq!(
+        files["Vo_axe_old.mp3"].audio.duration,
+        Some(std::time::Duration::from_millis(1_500))
+    );
+    assert!(!files.contains_key("Vo axe gone.mp3"));
+}
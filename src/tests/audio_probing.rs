@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use crate::audio::{AudioFormat, AudioInfo};
+
+/// An MPEG 1 layer III frame header at 128kbit/s and 44.1kHz, in stereo
+const MP3_FRAME: [u8; 4] = [0xff, 0xfb, 0x90, 0x00];
+
+fn ogg_page(granule: u64, packet: &[u8]) -> Vec<u8> {
+    let mut page = b"OggS".to_vec();
+    page.extend([0, 0]);
+    page.extend(granule.to_le_bytes());
+    page.extend([0; 12]);
+    page.push(1);
+    page.push(packet.len() as u8);
+    page.extend(packet);
+    page
+}
+
+#[test]
+fn test_constant_bitrate_mp3() {
+    let mut file = b"ID3\x04\x00\x00\x00\x00\x00\x0a".to_vec();
+    file.extend([0; 10]);
+    file.extend(MP3_FRAME);
+    // two seconds at 128kbit/s, less the header already written
+    file.resize(20 + 32_000, 0);
+
+    let info = AudioInfo::probe(&file);
+    assert_eq!(info.format(), Some(AudioFormat::Mp3));
+    assert_eq!(info.size, Some(32_020));
+    assert_eq!(info.duration, Some(Duration::from_secs(2)));
+}
+
+#[test]
+fn test_variable_bitrate_mp3() {
+    let mut file = MP3_FRAME.to_vec();
+    file.extend([0; 32]);
+    file.extend(b"Xing");
+    file.extend(1u32.to_be_bytes());
+    // 1152 samples a frame, so this many frames make three seconds
+    file.extend((44_100u32 * 3 / 1152 + 1).to_be_bytes());
+    file.resize(4000, 0);
+
+    let duration = AudioInfo::probe(&file).duration.unwrap();
+    assert_eq!(duration.as_secs(), 3);
+}
+
+#[test]
+fn test_ogg() {
+    let mut vorbis = b"\x01vorbis".to_vec();
+    vorbis.extend(0u32.to_le_bytes());
+    vorbis.push(2);
+    vorbis.extend(48_000u32.to_le_bytes());
+    let mut file = ogg_page(0, &vorbis);
+    file.extend(ogg_page(24_000, b"audio"));
+    file.extend(ogg_page(72_000, b"audio"));
+
+    let info = AudioInfo::probe(&file);
+    assert_eq!(info.format(), Some(AudioFormat::Ogg));
+    assert_eq!(info.duration, Some(Duration::from_millis(1500)));
+
+    let mut opus = b"OpusHead\x01\x02".to_vec();
+    opus.extend(312u16.to_le_bytes());
+    let head = ogg_page(0, &opus);
+    let tail = ogg_page(48_312, b"audio");
+    let duration = AudioFormat::Ogg.duration(&head, &tail, 0);
+    assert_eq!(duration, Some(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_wav() {
+    let mut file = b"RIFF\0\0\0\0WAVE".to_vec();
+    file.extend(b"fmt ");
+    file.extend(16u32.to_le_bytes());
+    file.extend([1, 0, 1, 0]);
+    file.extend(8_000u32.to_le_bytes());
+    // byte rate
+    file.extend(16_000u32.to_le_bytes());
+    file.extend([2, 0, 16, 0]);
+    file.extend(b"data");
+    file.extend(8_000u32.to_le_bytes());
+    file.resize(file.len() + 8_000, 0);
+
+    let info = AudioInfo::probe(&file);
+    assert_eq!(info.format(), Some(AudioFormat::Wav));
+    assert_eq!(info.duration, Some(Duration::from_millis(500)));
+}
+
+#[test]
+fn test_unknown_audio() {
+    let info = AudioInfo::probe(b"<html>Not found</html>");
+    assert_eq!(info.format(), None);
+    assert_eq!(info.duration, None);
+
+    assert_eq!(AudioFormat::from_mime("application/ogg"), Some(AudioFormat::Ogg));
+    assert_eq!(AudioFormat::from_mime("audio/mpeg"), Some(AudioFormat::Mp3));
+    assert_eq!(AudioFormat::from_mime("image/png"), None);
+}
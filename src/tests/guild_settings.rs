@@ -0,0 +1,140 @@
+use std::time::{Duration, Instant};
+
+use crate::cooldown::CooldownTracker;
+use crate::guild::{ClipMode, Cooldown, CustomReply, CustomResponse, GuildSettings, MatchMode};
+
+#[test]
+fn test_custom_response() {
+    let custom = |trigger: &str, reply| CustomResponse {
+        trigger: crate::process_text(trigger),
+        reply,
+        author: "1".to_string(),
+    };
+    let settings = GuildSettings {
+        custom_responses: vec![
+            custom("Good morning!", CustomReply::Text("gm".to_string())),
+            custom(
+                "gg",
+                CustomReply::Response {
+                    file: "Vo abaddon abad win 01.mp3".to_string(),
+                },
+            ),
+        ],
+        ..Default::default()
+    };
+    let found = |text: &str| {
+        settings
+            .custom_response(&crate::process_text(text))
+            .map(|c| c.reply.clone())
+    };
+    assert_eq!(
+        found("good MORNING"),
+        Some(CustomReply::Text("gm".to_string()))
+    );
+    assert!(matches!(found("GG!"), Some(CustomReply::Response { .. })));
+    assert_eq!(found("good morning everyone"), None);
+}
+
+#[test]
+fn test_guild_settings_defaults() {
+    // settings saved before a field existed still load
+    let settings: GuildSettings = ron::from_str("(language: Some(\"ru\"))").unwrap();
+    assert_eq!(settings.language.as_deref(), Some("ru"));
+    assert!(settings.custom_responses.is_empty());
+    assert!(settings.triggers);
+    assert_eq!(settings.clips, ClipMode::Thread);
+    assert_eq!(settings.match_mode, MatchMode::Exact);
+    assert!(settings.user_cooldown.is_none());
+
+    let saved = ron::to_string(&GuildSettings {
+        custom_responses: vec![CustomResponse {
+            trigger: "hi".to_string(),
+            reply: CustomReply::Audio {
+                file_name: "hi.mp3".to_string(),
+                hash: "abc".to_string(),
+            },
+            author: "1".to_string(),
+        }],
+        ..Default::default()
+    })
+    .unwrap();
+    let loaded: GuildSettings = ron::from_str(&saved).unwrap();
+    assert_eq!(
+        loaded.custom_response("hi").unwrap().reply.describe(),
+        "audio: hi.mp3"
+    );
+}
+
+#[test]
+fn test_channel_rules() {
+    let chain = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+    // a thread, its channel and the channel's category
+    let thread = chain(&["3", "2", "1"]);
+    let mut settings = GuildSettings::default();
+    assert!(!settings.has_channel_rules());
+    assert!(settings.channel_allowed(&thread));
+
+    settings.denied_channels = vec!["1".to_string()];
+    assert!(!settings.channel_allowed(&thread));
+    assert!(settings.channel_allowed(&chain(&["4"])));
+
+    // the closest rule wins
+    settings.allowed_channels = vec!["2".to_string()];
+    assert!(settings.channel_allowed(&thread));
+    assert!(!settings.channel_allowed(&chain(&["5", "1"])));
+    // nothing else is allowed once something is
+    assert!(!settings.channel_allowed(&chain(&["4"])));
+
+    settings.denied_channels.push("3".to_string());
+    assert!(!settings.channel_allowed(&thread));
+
+    // settings saved before denylists keep their channels
+    let loaded: GuildSettings = ron::from_str(r#"(channels: ["2"])"#).unwrap();
+    assert_eq!(loaded.allowed_channels, vec!["2".to_string()]);
+}
+
+#[test]
+fn test_cooldown() {
+    let cooldown = Cooldown {
+        messages: 2,
+        seconds: 10,
+    };
+    let start = Instant::now();
+    let mut tracker = CooldownTracker::default();
+    assert!(tracker.ready(&1, cooldown, start));
+    tracker.responded(1, start);
+
+    let later = start + Duration::from_secs(60);
+    tracker.message(&1);
+    tracker.message(&1);
+    assert!(!tracker.ready(&1, cooldown, later));
+    // other channels keep their own count
+    assert!(tracker.ready(&2, cooldown, start));
+    tracker.message(&1);
+    assert!(tracker.ready(&1, cooldown, later));
+    assert!(!tracker.ready(&1, cooldown, start + Duration::from_secs(5)));
+
+    tracker.responded(1, later);
+    tracker.message(&1);
+    assert!(tracker.ready(&1, Cooldown::default(), later));
+}
+
+#[test]
+fn test_user_cooldown() {
+    let cooldown = Cooldown {
+        messages: 1,
+        seconds: 0,
+    };
+    let start = Instant::now();
+    let mut tracker = CooldownTracker::default();
+    // the same user in two guilds
+    let (here, there) = ((Some(1), 7), (Some(2), 7));
+    tracker.responded(here, start);
+    assert!(!tracker.ready(&here, cooldown, start));
+    assert!(tracker.ready(&there, cooldown, start));
+
+    tracker.message(&here);
+    assert!(!tracker.ready(&here, cooldown, start));
+    tracker.message(&here);
+    assert!(tracker.ready(&here, cooldown, start));
+}
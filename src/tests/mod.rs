@@ -1,3 +1,11 @@
 #![cfg(test)]
 
+mod audio_cache;
+mod audio_probing;
+mod guild_settings;
+mod page_classification;
+mod parser_properties;
+mod response_lookup;
 mod response_parsing;
+mod text_processing;
+mod transcoding;
@@ -0,0 +1,86 @@
+use crate::response::{classify_page, PageInfo, PageKind};
+
+fn info(name: &str, kind: PageKind, parent: Option<&str>, variant: Option<&str>) -> PageInfo {
+    PageInfo {
+        name: name.to_string(),
+        kind,
+        parent: parent.map(str::to_string),
+        variant: variant.map(str::to_string),
+        language: "en".to_string(),
+    }
+}
+
+#[test]
+fn test_classify_page() {
+    let input = [
+        ("Abaddon/Responses", info("Abaddon", PageKind::Hero, None, None)),
+        (
+            "Juggernaut/Responses/Bladeform Legacy",
+            info(
+                "Juggernaut (Bladeform Legacy)",
+                PageKind::Arcana,
+                Some("Juggernaut"),
+                Some("Bladeform Legacy"),
+            ),
+        ),
+        (
+            "Anti-Mage/Wei Persona/Responses",
+            info(
+                "Anti-Mage (Wei Persona)",
+                PageKind::Persona,
+                Some("Anti-Mage"),
+                Some("Wei Persona"),
+            ),
+        ),
+        (
+            "Axe Announcer Pack",
+            info("Axe Announcer Pack", PageKind::Announcer, Some("Axe"), Some("Axe Announcer Pack")),
+        ),
+        (
+            "Axe Mega-Kill Announcer Pack",
+            info(
+                "Axe Mega-Kill Announcer Pack",
+                PageKind::MegaKill,
+                Some("Axe"),
+                Some("Axe Mega-Kill Announcer Pack"),
+            ),
+        ),
+        (
+            "Pudge/Responses/Diretide",
+            info("Pudge (Diretide)", PageKind::Event, Some("Pudge"), Some("Diretide")),
+        ),
+        ("Chat Wheel", info("Chat Wheel", PageKind::Hero, None, None)),
+    ];
+
+    for (page, expected) in input {
+        assert_eq!(classify_page(page), expected);
+    }
+}
+
+#[test]
+fn test_classify_smite_page() {
+    use crate::response::classify_smite_page;
+
+    let input = [
+        (
+            ("Agni voicelines", "Category:God voicelines"),
+            info("Agni", PageKind::Hero, None, None),
+        ),
+        (
+            ("Agni/Dragon Fire voicelines", "Category:Skin voicelines"),
+            info("Agni (Dragon Fire)", PageKind::Skin, Some("Agni"), Some("Dragon Fire")),
+        ),
+        (
+            ("Dragon Fire Agni voicelines", "Category:Skin voicelines"),
+            info("Dragon Fire Agni", PageKind::Skin, None, Some("Dragon Fire Agni")),
+        ),
+        (
+            ("Ymir Announcer voicelines", "Category:Announcer packs"),
+            info("Ymir Announcer", PageKind::VoicePack, None, Some("Ymir Announcer")),
+        ),
+    ];
+
+    for ((page, category), expected) in input {
+        assert_eq!(classify_smite_page(page, category), expected);
+    }
+}
@@ -0,0 +1,78 @@
+//! The parser runs over whatever the wiki hands it, so it must never panic or
+//! hang, no matter how broken the markup is
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+use proptest::prelude::*;
+
+use crate::parsing::{parse_all_response_lines, parse_response, parse_response_line};
+
+/// Pieces of wikitext the parser has to deal with, including the unbalanced
+/// halves of every tag it knows about
+const TOKENS: &[&str] = &[
+    "{{", "}}", "{", "}", "[[", "]]", "[", "]", "<", ">", "</", "<sm2>", "</sm2>", "<br>",
+    "<small>", "</small>", "|", "| ", "| align=\"left\" | \"", "* <sm2>", "''", "'''", "==",
+    "===", "\n", "\r\n", "\\n", " ", "resp|r", "Hero icon|Pudge", "link=Mist Coil", "File:x.png",
+    "VVGG", "Abaddon", "vo_abaddon_01.mp3", "…", "Привет",
+];
+
+fn wikitext() -> impl Strategy<Value = String> {
+    prop_oneof![
+        prop::collection::vec(prop::sample::select(TOKENS), 0..64).prop_map(|t| t.concat()),
+        "[\\[\\]{}<>|'=*/ a-z0-9\n\r]{0,128}",
+        any::<String>(),
+    ]
+}
+
+/// Runs the parser on another thread so a parser stuck in a loop fails the
+/// test instead of hanging it
+fn finishes<T: Send + 'static>(input: &str, parse: fn(&mut &str) -> T) -> T {
+    let (tx, rx) = mpsc::channel();
+    let owned = input.to_string();
+    std::thread::spawn(move || {
+        let _ = tx.send(parse(&mut owned.as_str()));
+    });
+    match rx.recv_timeout(Duration::from_secs(5)) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => panic!("parser did not finish on {:?}", input),
+        Err(mpsc::RecvTimeoutError::Disconnected) => panic!("parser panicked on {:?}", input),
+    }
+}
+
+proptest! {
+    #[test]
+    fn parse_response_terminates(input in wikitext()) {
+        let _ = finishes(&input, |input| parse_response(input).map_err(|e| e.to_string()));
+    }
+
+    #[test]
+    fn parse_response_line_terminates(input in wikitext()) {
+        let lines = [format!("* <sm2>{}", input), format!("| {}", input), input];
+        for line in lines {
+            let _ = finishes(&line, |input| parse_response_line(input).map_err(|e| e.to_string()));
+        }
+    }
+
+    #[test]
+    fn parse_all_response_lines_diagnoses(input in wikitext()) {
+        let line_count = input.split(['\n', '\r']).count() + input.matches("\\n").count();
+        let page = finishes(&input, parse_all_response_lines);
+        for diagnostic in page.diagnostics {
+            prop_assert!(diagnostic.line >= 1 && diagnostic.line <= line_count);
+            prop_assert!(!diagnostic.error.is_empty());
+        }
+    }
+}
+
+#[test]
+fn test_unbalanced_markup() {
+    let input = [
+        "a {b", "[[unclosed", "[unclosed", "<unclosed", "<small>unclosed", "line\nbreak",
+        "{{resp|r", "}} ]] >", "\r",
+    ];
+    for input in input {
+        let _ = finishes(input, |input| parse_response(input).map_err(|e| e.to_string()));
+    }
+    assert_eq!(finishes("a {b", |i| parse_response(i).unwrap()), "a {b");
+}
@@ -0,0 +1,250 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::response::{Game, Hero, PageKind, Response, ResponseDatabase, ResponseFilter};
+use crate::Data;
+
+fn response(id: i32, hero_id: i32, text: &str) -> Response {
+    Response {
+        id,
+        processed_text: crate::process_text(text),
+        original_text: text.to_string(),
+        rich_text: Default::default(),
+        file: String::new(),
+        response_link: format!("https://example.com/{id}.mp3"),
+        hero_id,
+        category: vec![],
+        meta: Default::default(),
+        vgs: None,
+        language: "en".to_string(),
+        audio: Default::default(),
+    }
+}
+
+fn database(responses: Vec<Response>) -> ResponseDatabase {
+    ResponseDatabase {
+        responses,
+        heroes: HashMap::new(),
+        icons: HashMap::new(),
+        dead_links: HashSet::new(),
+    }
+}
+
+fn covered(db: &ResponseDatabase, text: &str, filter: &ResponseFilter) -> Result<Vec<i32>, String> {
+    db.cover_sentence(text, filter)
+        .map(|pieces| pieces.iter().map(|r| r.id).collect())
+}
+
+#[test]
+fn test_cover_sentence() {
+    let db = database(vec![
+        response(1, 1, "Rampage!"),
+        response(2, 1, "Go"),
+        response(3, 1, "Go go go!"),
+        response(4, 2, "Go go"),
+        response(5, 2, "Not yet."),
+        response(6, 2, "Rampage"),
+    ]);
+    let filter = ResponseFilter::default();
+
+    // the longest line wins, even when shorter ones would also fit
+    assert_eq!(covered(&db, "go go go, not yet", &filter), Ok(vec![3, 5]));
+    assert_eq!(covered(&db, "Go go go go go", &filter), Ok(vec![3, 4]));
+    assert_eq!(covered(&db, "", &filter), Ok(vec![]));
+    assert_eq!(
+        covered(&db, "go go go, not now", &filter),
+        Err("not".to_string())
+    );
+
+    let hero_one = ResponseFilter {
+        heroes: vec![1],
+        ..Default::default()
+    };
+    assert_eq!(covered(&db, "go go go go go", &hero_one), Ok(vec![3, 2, 2]));
+    assert_eq!(covered(&db, "rampage", &hero_one), Ok(vec![1]));
+    assert_eq!(covered(&db, "not yet", &hero_one), Err("not".to_string()));
+}
+
+fn hero(id: i32, name: &str, kind: &str, game: &str, parent: Option<&str>) -> Hero {
+    let parent = parent.map_or("None".to_string(), |p| format!("Some({:?})", p));
+    ron::from_str(&format!(
+        "(id: {id}, hero_name: {name:?}, img_path: \"\", kind: {kind}, game: {game}, parent: {parent})"
+    ))
+    .unwrap()
+}
+
+#[test]
+fn test_guild_filter() {
+    let mut db = database(vec![
+        response(1, 1, "Axe is all the army I need"),
+        response(2, 2, "Let the battle begin"),
+        response(3, 3, "Let the battle begin"),
+        response(4, 4, "Axe is all the army I need"),
+    ]);
+    db.heroes = HashMap::from([
+        (1, hero(1, "Axe", "Hero", "Dota", None)),
+        (
+            2,
+            hero(2, "Axe announcer pack", "Announcer", "Dota", Some("Axe")),
+        ),
+        (3, hero(3, "Ares", "Hero", "Smite", None)),
+        (
+            4,
+            hero(4, "Bastion Announcer Pack", "Announcer", "Dota", None),
+        ),
+    ]);
+    let mut data = Data {
+        response_database: db,
+        ..Default::default()
+    };
+    let ids = |data: &Data, text: &str, filter: ResponseFilter| {
+        let filter = data.guild_filter(Some(1), filter).unwrap();
+        let mut ids = data
+            .response_database
+            .responses
+            .iter()
+            .filter(|r| r.processed_text == crate::process_text(text))
+            .filter(|r| filter.matches(r, data.response_database.heroes.get(&r.hero_id)))
+            .map(|r| r.id)
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids
+    };
+    let battle = "let the battle begin";
+    assert_eq!(ids(&data, battle, ResponseFilter::default()), vec![2, 3]);
+
+    data.guild_settings_mut(1).games = vec![Game::Smite];
+    assert_eq!(ids(&data, battle, ResponseFilter::default()), vec![3]);
+    assert_eq!(
+        data.get_guild_response(&crate::process_text(battle), Some(1))
+            .map(|r| r.id),
+        Some(3)
+    );
+    // other guilds are left alone
+    assert!(data
+        .guild_filter(Some(2), ResponseFilter::default())
+        .unwrap()
+        .games
+        .is_empty());
+
+    data.guild_settings_mut(1).games.clear();
+    data.guild_settings_mut(1).excluded_kinds = vec![PageKind::Announcer];
+    assert_eq!(ids(&data, battle, ResponseFilter::default()), vec![3]);
+    assert_eq!(
+        ids(
+            &data,
+            "axe is all the army i need",
+            ResponseFilter::default()
+        ),
+        vec![1]
+    );
+
+    data.guild_settings_mut(1).excluded_kinds.clear();
+    data.guild_settings_mut(1).heroes = vec!["axe".to_string()];
+    assert_eq!(ids(&data, battle, ResponseFilter::default()), vec![2]);
+    let ares = ResponseFilter {
+        heroes: vec![3],
+        ..Default::default()
+    };
+    assert!(data.guild_filter(Some(1), ares).is_none());
+}
+
+#[test]
+fn test_find_in_text() {
+    let db = database(vec![
+        response(1, 1, "Go go go!"),
+        response(2, 1, "Let the battle begin"),
+        response(3, 1, "Let the battle begin, and end"),
+        response(4, 1, "Yes"),
+    ]);
+    let filter = ResponseFilter::default();
+    let found = |text: &str| {
+        db.find_in_text(&crate::process_text(text), 3, &filter)
+            .map(|r| r.id)
+    };
+    assert_eq!(found("ok let the battle begin everyone"), Some(2));
+    assert_eq!(found("let the battle begin and end"), Some(3));
+    assert_eq!(found("go go go"), Some(1));
+    // only whole words count
+    assert_eq!(found("let the battle beginning"), None);
+    // and short lines are left out
+    assert_eq!(found("yes yes yes"), None);
+}
+
+#[test]
+fn test_category_filter() {
+    let mut killing = response(1, 1, "Cut down");
+    killing.category = vec!["Killing an enemy".to_string(), "Last hit".to_string()];
+    let mut spawning = response(2, 1, "Cut down");
+    spawning.category = vec!["Spawning".to_string()];
+    let db = database(vec![killing, spawning]);
+
+    let filter = ResponseFilter {
+        category: Some("last HIT".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(db.get_response("cut down", &filter).map(|r| r.id), Some(1));
+    let filter = ResponseFilter {
+        category: Some("Respawning".to_string()),
+        ..Default::default()
+    };
+    assert!(db.get_response("cut down", &filter).is_none());
+}
+
+#[test]
+fn test_about_filter() {
+    let mut inline = response(1, 1, "Nice try, Pudge.");
+    inline.meta.related_hero = Some("Pudge".to_string());
+    let mut item = response(2, 1, "Now you see me.");
+    item.meta.related_ability = Some("Blink Dagger".to_string());
+    let db = database(vec![inline, item]);
+
+    let about = |name: &str| ResponseFilter {
+        about: Some(name.to_string()),
+        ..Default::default()
+    };
+    // a hero named anywhere on the line counts, not only the one it's aimed at
+    assert_eq!(db.random_response(&about("pudge")).map(|r| r.id), Some(1));
+    assert_eq!(db.random_response(&about("Blink Dagger")).map(|r| r.id), Some(2));
+    assert!(db.random_response(&about("Lich")).is_none());
+}
+
+#[test]
+fn test_guild_reply() {
+    let targeting = |id: i32, hero_id: i32, text: &str, target: &str| {
+        let mut res = response(id, hero_id, text);
+        res.meta.target_hero = Some(target.to_string());
+        res
+    };
+    let mut russian = targeting(4, 2, "Абаддон, ты мой", "Abaddon");
+    russian.language = "ru".to_string();
+    let mut db = database(vec![
+        targeting(1, 1, "Not so fresh now, butcher.", "Pudge"),
+        targeting(2, 2, "Abaddon, you're mine", "Abaddon"),
+        targeting(3, 3, "Fresh Abaddon!", "Abaddon"),
+        russian,
+    ]);
+    db.heroes = HashMap::from([
+        (1, hero(1, "Abaddon", "Hero", "Dota", None)),
+        (2, hero(2, "Pudge", "Hero", "Dota", None)),
+        (3, hero(3, "Pudge (Feast of Abscession)", "Arcana", "Dota", Some("Pudge"))),
+    ]);
+    let mut data = Data {
+        response_database: db,
+        ..Default::default()
+    };
+    let kill = data.response_database.responses[0].clone();
+
+    data.guild_settings_mut(1).excluded_kinds = vec![PageKind::Arcana];
+    data.guild_settings_mut(1).language = Some("ru".to_string());
+    assert_eq!(data.get_guild_reply(&kill, Some(1)).map(|r| r.id), Some(4));
+
+    // falls back to any language, but never to a kind the guild left out
+    data.guild_settings_mut(1).language = Some("de".to_string());
+    for _ in 0..20 {
+        let reply = data.get_guild_reply(&kill, Some(1)).map(|r| r.id);
+        assert!(matches!(reply, Some(2 | 4)), "{reply:?}");
+    }
+
+    data.guild_settings_mut(1).heroes = vec!["Abaddon".to_string()];
+    assert!(data.get_guild_reply(&kill, Some(1)).is_none());
+}
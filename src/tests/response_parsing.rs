@@ -14,7 +14,7 @@ fn test_parse_response() {
     ];
 
     for (mut input, expected) in input.iter_mut() {
-        match crate::dota::parsing::parse_response(&mut input) {
+        match crate::parsing::parse_response(&mut input) {
             Ok(response) => {
                 assert_eq!(&response, expected);
             }
@@ -33,10 +33,10 @@ fn test_parse_response_line() {
     )];
 
     for (mut input, (expected_file, expected_response)) in input.iter_mut() {
-        match crate::dota::parsing::parse_response_line(&mut input) {
-            Ok((file, response)) => {
-                assert_eq!(file, expected_file.to_string());
-                assert_eq!(response, expected_response.to_string());
+        match crate::parsing::parse_response_line(&mut input) {
+            Ok(lines) => {
+                assert_eq!(&lines[0].file, expected_file);
+                assert_eq!(&lines[0].response, expected_response);
             }
             Err(e) => {
                 panic!("{:?}", e);
@@ -51,20 +51,192 @@ fn test_all_response_lines() {
     let mut input = r#"\n{{Tabs Hero}}\n\n{{VoiceNavSidebar}}{{TOC float|right}}\n\n== Loadout ==\n* <sm2>vo_abaddon_abad_spawn_01.mp3</sm2> Abaddon.\n\n== Drafting ==\n''Picked''\n* <sm2>vo_abaddon_abad_spawn_01.mp3</sm2> Abaddon.\n"#;
 
     let expected = [
-        ("Vo abaddon abad spawn 01.mp3", "Abaddon."),
-        ("Vo abaddon abad spawn 01.mp3", "Abaddon."),
+        ("Vo abaddon abad spawn 01.mp3", "Abaddon.", vec!["Loadout"]),
+        ("Vo abaddon abad spawn 01.mp3", "Abaddon.", vec!["Drafting", "Picked"]),
     ];
 
-    match crate::dota::parsing::parse_all_response_lines(&mut input) {
-        Ok(response_lines) => {
-            assert_eq!(response_lines.len(), expected.len());
-            for (actual, expected) in response_lines.iter().zip(expected.iter()) {
-                assert_eq!(actual.file, expected.0);
-                assert_eq!(actual.response, expected.1);
-            }
-        }
-        Err(e) => {
-            panic!("{:?}", e);
-        }
+    let page = crate::parsing::parse_all_response_lines(&mut input);
+    assert!(page.diagnostics.is_empty(), "{:?}", page.diagnostics);
+    assert_eq!(page.responses.len(), expected.len());
+    for (actual, expected) in page.responses.iter().zip(expected.iter()) {
+        assert_eq!(actual.file, expected.0);
+        assert_eq!(actual.response, expected.1);
+        assert_eq!(actual.category, expected.2);
+    }
+}
+
+#[test]
+fn test_response_line_categories() {
+    let mut input = r#"== Killing an opponent ==\n=== Allies ===\n* <sm2>vo_abaddon_abad_kill_01.mp3</sm2> Back to the mist.\n''Rare''\n* <sm2>vo_abaddon_abad_kill_02.mp3</sm2> Dust to dust.\n=== {{Hero icon|Pudge}} Enemies ===\n* <sm2>vo_abaddon_abad_kill_03.mp3</sm2> Rot in the fog.\n== Death ==\n* <sm2>vo_abaddon_abad_death_01.mp3</sm2> Avernus!\n"#;
+
+    let expected = [
+        vec!["Killing an opponent", "Allies"],
+        vec!["Killing an opponent", "Allies", "Rare"],
+        vec!["Killing an opponent", "Enemies"],
+        vec!["Death"],
+    ];
+
+    let response_lines = crate::parsing::parse_all_response_lines(&mut input).responses;
+    assert_eq!(response_lines.len(), expected.len());
+    for (actual, expected) in response_lines.iter().zip(expected.iter()) {
+        assert_eq!(&actual.category, expected);
+    }
+}
+
+#[test]
+fn test_parse_response_meta() {
+    use crate::parsing::{Rarity, ResponseMeta};
+
+    let mut input: Vec<(&str, ResponseMeta)> = vec![
+        (
+            " {{Hero icon|Abaddon|16px}} [[File:Mist Coil icon.png|16px|link=Mist Coil]] Reclaimed for Avernus.",
+            ResponseMeta {
+                related_hero: Some("Abaddon".to_string()),
+                target_hero: Some("Abaddon".to_string()),
+                related_ability: Some("Mist Coil".to_string()),
+                ..Default::default()
+            },
+        ),
+        (
+            "{{resp|u}} [[Shitty Wizard]]!",
+            ResponseMeta {
+                rarity: Some(Rarity::UltraRare),
+                ..Default::default()
+            },
+        ),
+        (
+            "{{resp|r|60}} {{Item icon|Blink Dagger}} Now you see me.",
+            ResponseMeta {
+                rarity: Some(Rarity::Rare),
+                cooldown: Some(60),
+                related_ability: Some("Blink Dagger".to_string()),
+                ..Default::default()
+            },
+        ),
+        (
+            "Nice try, {{Hero icon|Pudge|16px}} Pudge.",
+            ResponseMeta {
+                related_hero: Some("Pudge".to_string()),
+                ..Default::default()
+            },
+        ),
+        (
+            "{{resp|a}} Who goes there?",
+            ResponseMeta {
+                conditions: vec!["a".to_string()],
+                ..Default::default()
+            },
+        ),
+    ];
+
+    for (input, expected) in input.iter_mut() {
+        let (_, meta) = crate::parsing::parse_response_with_meta(input).unwrap();
+        assert_eq!(&meta, expected);
     }
 }
+
+#[test]
+fn test_all_response_lines_recovers() {
+    let mut input = "== Loadout ==\n* <sm2>vo_abaddon_abad_spawn_01.mp3 Abaddon.\n* <sm2>vo_abaddon_abad_spawn_02.mp3</sm2> Avernus.\n* <sm2></sm2> The feast never ends!\n| align=\"left\" | \"Unfinished row\"";
+
+    let page = crate::parsing::parse_all_response_lines(&mut input);
+    assert_eq!(page.responses.len(), 1);
+    assert_eq!(page.responses[0].response, "Avernus.");
+    let lines = page.diagnostics.iter().map(|d| d.line).collect::<Vec<_>>();
+    assert_eq!(lines, [2, 4, 5]);
+    assert_eq!(page.diagnostics[1].error, "empty <sm2> tag");
+}
+
+#[test]
+fn test_vgs_lines() {
+    let mut input = r#"{| class="wikitable"
+|-
+| '''VVGG'''
+| align="left" | "Good game!"
+| <sm2>Agni_VVGG.ogg</sm2>
+|-
+| align="left" | "Unbound"
+| <sm2>Agni_Other.ogg</sm2>
+|-
+| VEE | "Hello!" | <sm2>Agni_VEE.ogg</sm2>
+|}"#;
+
+    let page = crate::parsing::parse_all_response_lines(&mut input);
+    let vgs = page
+        .responses
+        .iter()
+        .map(|r| (r.response.as_str(), r.vgs.as_deref()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vgs,
+        [
+            ("\"Good game!\"", Some("VVGG")),
+            ("\"Unbound\"", None),
+            ("\"Hello!\"", Some("VEE")),
+        ]
+    );
+
+    let mut line = r#"| VEE | "Hello!" | <sm2>Agni_VEE.ogg</sm2>"#;
+    let parsed = crate::parsing::parse_response_line(&mut line).unwrap();
+    assert_eq!(parsed[0].vgs.as_deref(), Some("VEE"));
+
+    assert_eq!(crate::parsing::vgs_code("vvgg"), Some("VVGG".to_string()));
+    assert_eq!(crate::parsing::vgs_code("'''VEE'''"), Some("VEE".to_string()));
+    assert_eq!(crate::parsing::vgs_code(r#" align="left" "#), None);
+    assert_eq!(crate::parsing::vgs_code("hello"), None);
+}
+
+#[test]
+fn test_rich_text() {
+    use crate::parsing::Span;
+
+    let mut input = [
+        (
+            "'''RAMPAGE!'''",
+            vec![Span::Bold("RAMPAGE!".to_string())],
+            "RAMPAGE!",
+            "**RAMPAGE!**",
+        ),
+        (
+            "[laughs] You ''fool''.",
+            vec![
+                Span::StageDirection("laughs".to_string()),
+                Span::Plain(" You ".to_string()),
+                Span::Italic("fool".to_string()),
+                Span::Plain(".".to_string()),
+            ],
+            "laughs You fool.",
+            "*[laughs]* You *fool*.",
+        ),
+        (
+            "<small>(Low health)</small> <i>Ad</i> '''''Avernus'''''!",
+            vec![
+                Span::Annotation("(Low health)".to_string()),
+                Span::Italic("Ad".to_string()),
+                Span::Plain(" ".to_string()),
+                Span::BoldItalic("Avernus".to_string()),
+                Span::Plain("!".to_string()),
+            ],
+            "Ad Avernus!",
+            "*(Low health)* *Ad* ***Avernus***!",
+        ),
+        (
+            "It's a ''trap ''",
+            vec![
+                Span::Plain("It's a ".to_string()),
+                Span::Italic("trap ".to_string()),
+            ],
+            "It's a trap ",
+            "It's a *trap*",
+        ),
+    ];
+
+    for (input, spans, plain, markdown) in input.iter_mut() {
+        let (text, _) = crate::parsing::parse_rich_response(input).unwrap();
+        assert_eq!(&text.0, spans);
+        assert_eq!(&text.plain(), plain);
+        assert_eq!(&text.markdown(), markdown);
+    }
+
+    assert_eq!(crate::parsing::escape_markdown("*_~"), r"\*\_\~");
+}
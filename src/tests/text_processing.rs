@@ -0,0 +1,64 @@
+use crate::process_text;
+
+#[test]
+fn test_process_text() {
+    let input = [
+        ("Who goes there?", "who goes there"),
+        ("  This magic…disappoints.  ", "this magic disappoints"),
+        ("My concentration—shattered!", "my concentration shattered"),
+        ("It’s “fine”", "it s fine"),
+        ("Привет, мир!", "привет мир"),
+        ("ЗА АВЕРНУС!", "за авернус"),
+        ("Сила\tи\nслава", "сила и слава"),
+    ];
+
+    for (input, expected) in input {
+        assert_eq!(process_text(input), expected);
+    }
+}
+
+#[test]
+fn test_process_text_folding() {
+    let corpus = [
+        // diacritics
+        ("Señor", "senor"),
+        ("naïve café", "naive cafe"),
+        ("Ångström", "angstrom"),
+        ("Crème brûlée", "creme brulee"),
+        ("Þórr's Æther", "thorr s aether"),
+        ("Straße", "strasse"),
+        ("Łódź", "lodz"),
+        ("Øystein", "oystein"),
+        // ligatures
+        ("ﬁnal ﬂight", "final flight"),
+        ("Œuvre", "oeuvre"),
+        // full-width and other compatibility forms
+        ("ＦＩＲＳＴ ＢＬＯＯＤ", "first blood"),
+        ("ｄｏｔａ２", "dota2"),
+        ("ⅩⅡ", "xii"),
+        ("² ½", "2 1 2"),
+        // emoji and symbols
+        ("Rampage! 🔥🔥", "rampage"),
+        ("gg 👍🏽 wp", "gg wp"),
+        ("❤️ Avernus ❤️", "avernus"),
+        // cyrillic keeps its letters, only losing diacritics
+        ("Ёлка", "елка"),
+        // but й is a letter of its own, also when it comes decomposed
+        ("Йорк", "йорк"),
+        ("мой твои", "мой твои"),
+        ("и\u{306}ти", "йти"),
+    ];
+
+    for (input, expected) in corpus {
+        assert_eq!(process_text(input), expected, "folding {:?}", input);
+    }
+}
+
+#[test]
+fn test_process_text_is_idempotent() {
+    let input = ["Señor ﬁnal", "ＦＩＲＳＴ ＢＬＯＯＤ", "Ёлка 🔥", "It’s “fine”…"];
+    for input in input {
+        let once = process_text(input);
+        assert_eq!(process_text(&once), once);
+    }
+}
@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use crate::audio::{AudioFormat, AudioInfo};
+use crate::transcode::{ogg_crc, ogg_opus, Pcm, OPUS_FRAME, WAVEFORM_POINTS};
+
+/// A mono 16 bit wav file holding `samples`
+fn wav(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+    let data = samples.len() as u32 * 2;
+    let mut file = b"RIFF".to_vec();
+    file.extend((36 + data).to_le_bytes());
+    file.extend(b"WAVEfmt ");
+    file.extend(16u32.to_le_bytes());
+    file.extend([1, 0, 1, 0]);
+    file.extend(sample_rate.to_le_bytes());
+    file.extend((sample_rate * 2).to_le_bytes());
+    file.extend([2, 0, 16, 0]);
+    file.extend(b"data");
+    file.extend(data.to_le_bytes());
+    for sample in samples {
+        file.extend(sample.to_le_bytes());
+    }
+    file
+}
+
+#[test]
+fn test_decode_wav() {
+    let samples = (0..8_000)
+        .map(|i| ((i as f32 / 8.0).sin() * 16_000.0) as i16)
+        .collect::<Vec<_>>();
+    let pcm = Pcm::decode(&wav(&samples, 8_000)).unwrap();
+    assert_eq!(pcm.sample_rate, 8_000);
+    assert_eq!(pcm.samples.len(), 8_000);
+    assert_eq!(pcm.duration(), Duration::from_secs(1));
+    assert!((pcm.samples[100] - samples[100] as f32 / 32_768.0).abs() < 1e-4);
+
+    let resampled = pcm.resample(48_000);
+    assert_eq!(resampled.sample_rate, 48_000);
+    assert_eq!(resampled.samples.len(), 48_000);
+    assert_eq!(resampled.samples[600], pcm.samples[100]);
+
+    assert!(Pcm::decode(b"<html>Not found</html>").is_err());
+}
+
+#[test]
+fn test_waveform() {
+    let quiet_then_loud = Pcm {
+        samples: [vec![0.1; 1_000], vec![0.8; 1_000]].concat(),
+        sample_rate: 8_000,
+    };
+    let waveform = quiet_then_loud.waveform(WAVEFORM_POINTS);
+    assert_eq!(waveform.len(), 250);
+    assert_eq!(waveform[0], 32);
+    assert_eq!(waveform[249], 255);
+
+    let short = Pcm {
+        samples: vec![0.5; 10],
+        sample_rate: 8_000,
+    };
+    assert_eq!(short.waveform(WAVEFORM_POINTS), vec![255; 10]);
+
+    let silent = Pcm {
+        samples: vec![0.0; 1_000],
+        sample_rate: 8_000,
+    };
+    assert!(silent.waveform(WAVEFORM_POINTS).iter().all(|p| *p == 0));
+}
+
+#[test]
+fn test_ogg_crc() {
+    assert_eq!(ogg_crc(b"123456789"), 0x89a1_897f);
+}
+
+#[test]
+fn test_ogg_opus() {
+    // enough packets that they need several pages
+    let packets = (0..300).map(|i| vec![i as u8; 100]).collect::<Vec<_>>();
+    let samples = 300 * OPUS_FRAME as u64 - 500;
+    let file = ogg_opus(&packets, 312, samples);
+
+    let info = AudioInfo::probe(&file);
+    assert_eq!(info.format(), Some(AudioFormat::Ogg));
+    assert_eq!(
+        info.duration,
+        Some(Duration::from_secs_f64(samples as f64 / 48_000.0))
+    );
+
+    let mut pages = 0;
+    let mut offset = 0;
+    while offset < file.len() {
+        let page = &file[offset..];
+        assert!(page.starts_with(b"OggS"));
+        let segments = page[26] as usize;
+        let body = page[27..27 + segments]
+            .iter()
+            .map(|l| *l as usize)
+            .sum::<usize>();
+        let length = 27 + segments + body;
+        let mut unchecked = page[..length].to_vec();
+        let crc = u32::from_le_bytes(unchecked[22..26].try_into().unwrap());
+        unchecked[22..26].fill(0);
+        assert_eq!(ogg_crc(&unchecked), crc);
+        assert_eq!(u32::from_le_bytes(page[18..22].try_into().unwrap()), pages);
+        pages += 1;
+        offset += length;
+    }
+    assert!(pages > 3);
+}
+
+#[test]
+fn test_concat_to_wav() {
+    let beep = Pcm {
+        samples: vec![0.5; 4_000],
+        sample_rate: 8_000,
+    };
+    let boop = Pcm {
+        samples: vec![-0.5; 8_000],
+        sample_rate: 16_000,
+    };
+    let joined = Pcm::concat(&[beep, boop], Duration::from_millis(100), 8_000);
+    assert_eq!(joined.sample_rate, 8_000);
+    assert_eq!(joined.samples.len(), 4_000 + 800 + 4_000);
+    assert_eq!(joined.samples[4_400], 0.0);
+
+    let file = joined.to_wav();
+    let info = AudioInfo::probe(&file);
+    assert_eq!(info.format(), Some(AudioFormat::Wav));
+    assert_eq!(info.duration, Some(Duration::from_millis(1_100)));
+
+    let decoded = Pcm::decode(&file).unwrap();
+    assert_eq!(decoded.samples.len(), joined.samples.len());
+    assert!((decoded.samples[0] - 0.5).abs() < 1e-3);
+    assert!((decoded.samples[8_000] + 0.5).abs() < 1e-3);
+}
@@ -0,0 +1,335 @@
+use std::io::Cursor;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use symphonia::core::{
+    audio::SampleBuffer, codecs::DecoderOptions, errors::Error as DecodeError,
+    formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+
+use crate::audio::AudioFormat;
+
+/// The only sample rate Ogg Opus granule positions are counted in
+pub const OPUS_SAMPLE_RATE: u32 = 48_000;
+
+/// Samples in each encoded Opus packet, 20ms at 48kHz
+pub const OPUS_FRAME: usize = 960;
+
+/// Most points Discord shows in a voice message's waveform
+pub const WAVEFORM_POINTS: usize = 256;
+
+/// Serial number of the one logical stream in the Ogg files we write
+const OGG_SERIAL: u32 = 0x5348_4b42;
+
+/// Decoded audio, mixed down to a single channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pcm {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+impl Pcm {
+    /// Decodes an mp3, ogg or wav file
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut hint = Hint::new();
+        if let Some(format) = AudioFormat::sniff(bytes) {
+            hint.with_extension(format.extension());
+        }
+        let source =
+            MediaSourceStream::new(Box::new(Cursor::new(bytes.to_vec())), Default::default());
+        let probed = symphonia::default::get_probe().format(
+            &hint,
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?;
+        let mut format = probed.format;
+        let track = format
+            .default_track()
+            .ok_or_else(|| anyhow!("File has no audio track"))?;
+        let track_id = track.id;
+        let mut sample_rate = track.codec_params.sample_rate;
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())?;
+
+        let mut samples = vec![];
+        loop {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                Err(DecodeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    break
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if packet.track_id() != track_id {
+                continue;
+            }
+            let decoded = match decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // a corrupt frame only costs us a few milliseconds of audio
+                Err(DecodeError::DecodeError(e)) => {
+                    tracing::debug!("Skipping undecodable audio frame: {}", e);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let spec = *decoded.spec();
+            sample_rate = Some(spec.rate);
+            let channels = spec.channels.count().max(1);
+            let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+            buffer.copy_interleaved_ref(decoded);
+            samples.extend(
+                buffer
+                    .samples()
+                    .chunks(channels)
+                    .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+            );
+        }
+
+        Ok(Self {
+            samples,
+            sample_rate: sample_rate.ok_or_else(|| anyhow!("File has no sample rate"))?,
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.samples.len() as f64 / self.sample_rate as f64)
+    }
+
+    /// Converts to another sample rate by linear interpolation, which is plenty
+    /// for voice lines
+    pub fn resample(&self, sample_rate: u32) -> Self {
+        if sample_rate == self.sample_rate || self.samples.is_empty() {
+            return Self {
+                samples: self.samples.clone(),
+                sample_rate,
+            };
+        }
+        let ratio = self.sample_rate as f64 / sample_rate as f64;
+        let length = (self.samples.len() as f64 / ratio).round() as usize;
+        let last = self.samples.len() - 1;
+        let samples = (0..length)
+            .map(|i| {
+                let position = i as f64 * ratio;
+                let index = (position as usize).min(last);
+                let next = (index + 1).min(last);
+                let fraction = (position - index as f64) as f32;
+                self.samples[index] * (1.0 - fraction) + self.samples[next] * fraction
+            })
+            .collect();
+        Self {
+            samples,
+            sample_rate,
+        }
+    }
+
+    /// Joins clips one after another with `gap` of silence between them, at
+    /// `sample_rate`
+    pub fn concat(clips: &[Pcm], gap: Duration, sample_rate: u32) -> Self {
+        let silence = (gap.as_secs_f64() * sample_rate as f64).round() as usize;
+        let mut samples = vec![];
+        for (i, clip) in clips.iter().enumerate() {
+            if i > 0 {
+                samples.resize(samples.len() + silence, 0.0);
+            }
+            samples.extend(clip.resample(sample_rate).samples);
+        }
+        Self {
+            samples,
+            sample_rate,
+        }
+    }
+
+    /// Encodes the audio as a 16 bit mono wav file
+    pub fn to_wav(&self) -> Vec<u8> {
+        let data = self.samples.len() as u32 * 2;
+        let mut file = Vec::with_capacity(44 + data as usize);
+        file.extend(b"RIFF");
+        file.extend((36 + data).to_le_bytes());
+        file.extend(b"WAVEfmt ");
+        file.extend(16u32.to_le_bytes());
+        // uncompressed, one channel
+        file.extend([1, 0, 1, 0]);
+        file.extend(self.sample_rate.to_le_bytes());
+        // bytes per second, bytes per sample and bits per sample
+        file.extend((self.sample_rate * 2).to_le_bytes());
+        file.extend([2, 0, 16, 0]);
+        file.extend(b"data");
+        file.extend(data.to_le_bytes());
+        for sample in &self.samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            file.extend(sample.to_le_bytes());
+        }
+        file
+    }
+
+    /// The loudness of the audio over time, as up to `points` values where the
+    /// loudest stretch is 255
+    pub fn waveform(&self, points: usize) -> Vec<u8> {
+        if self.samples.is_empty() || points == 0 {
+            return vec![];
+        }
+        let chunk = self.samples.len().div_ceil(points);
+        let levels = self
+            .samples
+            .chunks(chunk)
+            .map(|chunk| (chunk.iter().map(|s| s * s).sum::<f32>() / chunk.len() as f32).sqrt())
+            .collect::<Vec<_>>();
+        let loudest = levels.iter().copied().fold(0.0, f32::max);
+        if loudest == 0.0 {
+            return vec![0; levels.len()];
+        }
+        levels
+            .iter()
+            .map(|level| (level / loudest * 255.0).round() as u8)
+            .collect()
+    }
+}
+
+/// The CRC Ogg pages are checked with: polynomial 0x04c11db7, no reflection,
+/// starting from 0 and not inverted at the end
+pub(crate) fn ogg_crc(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0u32, |crc, byte| {
+        let mut crc = crc ^ ((*byte as u32) << 24);
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Writes the pages of a single logical Ogg stream
+struct OggWriter {
+    out: Vec<u8>,
+    sequence: u32,
+}
+
+impl OggWriter {
+    const CONTINUED: u8 = 0x01;
+    const FIRST: u8 = 0x02;
+    const LAST: u8 = 0x04;
+
+    /// Writes one page holding whole `packets`, which must need no more than
+    /// 255 lacing values between them
+    fn page(&mut self, packets: &[&[u8]], granule: u64, flags: u8) {
+        let mut lacing = vec![];
+        for packet in packets {
+            lacing.extend(std::iter::repeat_n(255, packet.len() / 255));
+            lacing.push((packet.len() % 255) as u8);
+        }
+        debug_assert!(lacing.len() <= 255);
+
+        let start = self.out.len();
+        self.out.extend(b"OggS");
+        self.out.push(0);
+        self.out.push(flags & !Self::CONTINUED);
+        self.out.extend(granule.to_le_bytes());
+        self.out.extend(OGG_SERIAL.to_le_bytes());
+        self.out.extend(self.sequence.to_le_bytes());
+        let crc_at = self.out.len();
+        self.out.extend([0; 4]);
+        self.out.push(lacing.len() as u8);
+        self.out.extend(lacing);
+        for packet in packets {
+            self.out.extend(*packet);
+        }
+        let crc = ogg_crc(&self.out[start..]);
+        self.out[crc_at..crc_at + 4].copy_from_slice(&crc.to_le_bytes());
+        self.sequence += 1;
+    }
+}
+
+/// Wraps mono Opus packets of [`OPUS_FRAME`] samples each into an Ogg Opus
+/// file. `pre_skip` is the encoder's lookahead and `samples` the length of the
+/// audio before it was padded out to whole packets
+pub fn ogg_opus(packets: &[Vec<u8>], pre_skip: u16, samples: u64) -> Vec<u8> {
+    let mut writer = OggWriter {
+        out: vec![],
+        sequence: 0,
+    };
+
+    let mut head = b"OpusHead".to_vec();
+    head.push(1);
+    // mono
+    head.push(1);
+    head.extend(pre_skip.to_le_bytes());
+    head.extend(OPUS_SAMPLE_RATE.to_le_bytes());
+    // output gain, then channel mapping family 0
+    head.extend([0, 0, 0]);
+    writer.page(&[&head], 0, OggWriter::FIRST);
+
+    let vendor = env!("CARGO_PKG_NAME");
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend((vendor.len() as u32).to_le_bytes());
+    tags.extend(vendor.as_bytes());
+    tags.extend(0u32.to_le_bytes());
+    writer.page(&[&tags], 0, 0);
+
+    let end = pre_skip as u64 + samples;
+    let mut granule = 0u64;
+    let mut page: Vec<&[u8]> = vec![];
+    let mut lacing = 0;
+    for (i, packet) in packets.iter().enumerate() {
+        let needed = packet.len() / 255 + 1;
+        if lacing + needed > 255 {
+            writer.page(&page, granule.min(end), 0);
+            page.clear();
+            lacing = 0;
+        }
+        page.push(packet);
+        lacing += needed;
+        granule += OPUS_FRAME as u64;
+        if i == packets.len() - 1 {
+            writer.page(&page, granule.min(end), OggWriter::LAST);
+        }
+    }
+    if packets.is_empty() {
+        writer.page(&[], 0, OggWriter::LAST);
+    }
+    writer.out
+}
+
+/// A clip ready to be sent as a Discord voice message
+#[cfg(feature = "voice-messages")]
+#[derive(Debug, Clone)]
+pub struct VoiceMessage {
+    /// An Ogg Opus file
+    pub ogg: Vec<u8>,
+    pub duration: Duration,
+    pub waveform: Vec<u8>,
+}
+
+#[cfg(feature = "voice-messages")]
+impl VoiceMessage {
+    /// Decodes an mp3, ogg or wav file and re-encodes it as mono Ogg Opus
+    pub fn encode(bytes: &[u8]) -> Result<Self> {
+        use audiopus::{coder::Encoder, Application, Channels, SampleRate};
+
+        let pcm = Pcm::decode(bytes)?.resample(OPUS_SAMPLE_RATE);
+        let encoder = Encoder::new(SampleRate::Hz48000, Channels::Mono, Application::Audio)?;
+        let pre_skip = encoder.lookahead()? as usize;
+
+        // pad so the lookahead is flushed and the last packet is whole
+        let mut samples = pcm.samples.clone();
+        let padded = (samples.len() + pre_skip).div_ceil(OPUS_FRAME) * OPUS_FRAME;
+        samples.resize(padded, 0.0);
+        let mut buffer = [0; 4000];
+        let packets = samples
+            .chunks(OPUS_FRAME)
+            .map(|frame| {
+                let length = encoder.encode_float(frame, &mut buffer)?;
+                Ok(buffer[..length].to_vec())
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            ogg: ogg_opus(&packets, pre_skip as u16, pcm.samples.len() as u64),
+            duration: pcm.duration(),
+            waveform: pcm.waveform(WAVEFORM_POINTS),
+        })
+    }
+}